@@ -1,33 +1,79 @@
-#[derive(Debug)]
+mod mod_10_7 {
+    pub mod building;
+    pub mod controller;
+    pub mod event_log;
+    pub mod group;
+    pub mod safety;
+    pub mod scheduler;
+    pub mod simulation;
+}
+
+use mod_10_7::building::Building;
+use mod_10_7::controller::ElevatorController;
+use mod_10_7::event_log::{read_log, replay};
+use mod_10_7::group::{GroupDispatcher, WeightedCost};
+use mod_10_7::safety::{check_trace, SafetyRules};
+use mod_10_7::scheduler::scheduler_by_name;
+use mod_10_7::simulation::{simulate, SimConfig, Traffic};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 /// An event in the elevator system that the controller must react to.
 enum Event {
-    CarArrived(i32),
-    CarDoorOpened,
-    CarDoorClosed,
+    CarArrived(CarId, i32),
+    CarDoorOpened(CarId),
+    CarDoorClosed(CarId),
     LobbyCallButtonPressed(i32, Direction),
-    CarFloorButtonPressed(i32),
+    CarFloorButtonPressed(CarId, i32),
+    FireRecallActivated,
+    FireRecallReset,
+    CarModeSelected(CarId, CarMode),
 }
 
+/// Identifies one car in a bank of elevators.
+type CarId = usize;
+
 /// A direction of travel.
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum Direction {
     Up,
     Down,
 }
 
+/// A position of the key switch inside a car.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CarMode {
+    Normal,
+    /// Fire recall phase II: a firefighter operates the car from inside.
+    FirefighterService,
+    /// The car is taken out of the group and driven by an attendant.
+    IndependentService,
+    /// The car is out of service for maintenance.
+    OutOfService,
+}
+
+impl Direction {
+    /// The opposite direction of travel.
+    fn reverse(self) -> Direction {
+        match self {
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+        }
+    }
+}
+
 /// The car has arrived on the given floor.
-fn car_arrived(floor: i32) -> Event {
-    Event::CarArrived(floor)
+fn car_arrived(car: CarId, floor: i32) -> Event {
+    Event::CarArrived(car, floor)
 }
 
 /// The car doors have opened.
-fn car_door_opened() -> Event {
-    Event::CarDoorOpened
+fn car_door_opened(car: CarId) -> Event {
+    Event::CarDoorOpened(car)
 }
 
 /// The car doors have closed.
-fn car_door_closed() -> Event {
-    Event::CarDoorClosed
+fn car_door_closed(car: CarId) -> Event {
+    Event::CarDoorClosed(car)
 }
 
 /// A directional button was pressed in an elevator lobby on the given floor.
@@ -36,21 +82,256 @@ fn lobby_call_button_pressed(floor: i32, dir: Direction) -> Event {
 }
 
 /// A floor button was pressed in the elevator car.
-fn car_floor_button_pressed(floor: i32) -> Event {
-    Event::CarFloorButtonPressed(floor)
+fn car_floor_button_pressed(car: CarId, floor: i32) -> Event {
+    Event::CarFloorButtonPressed(car, floor)
 }
 
-fn main() {
+/// Fire recall phase I was activated, e.g. by a smoke detector: every car
+/// must return to the recall floor.
+fn fire_recall_activated() -> Event {
+    Event::FireRecallActivated
+}
+
+/// Fire recall phase I was reset.
+fn fire_recall_reset() -> Event {
+    Event::FireRecallReset
+}
+
+/// The key switch in the car was turned to the given mode.
+fn car_mode_selected(car: CarId, mode: CarMode) -> Event {
+    Event::CarModeSelected(car, mode)
+}
+
+/// Runs `simulate [TRAFFIC [SEED [SCHEDULER]]]` and prints the metrics.
+fn run_simulation(args: &[String]) -> Result<(), String> {
+    let mut config = SimConfig::default();
+    if let Some(name) = args.first() {
+        config.traffic = Traffic::from_name(name).ok_or_else(|| {
+            format!("unknown traffic {name:?}, expected up-peak, down-peak or inter-floor")
+        })?;
+    }
+    if let Some(seed) = args.get(1) {
+        config.seed = seed.parse().map_err(|e| format!("bad seed {seed:?}: {e}"))?;
+    }
+    if let Some(name) = args.get(2) {
+        config.scheduler = name.clone();
+    }
+    let report = simulate(&config)?;
     println!(
-        "A ground floor passenger has pressed the up button: {:?}",
-        lobby_call_button_pressed(0, Direction::Up)
+        "{:?} traffic, seed {}, {} cars scheduled with {}",
+        config.traffic, config.seed, config.cars, config.scheduler
     );
-    println!("The car has arrived on the ground floor: {:?}", car_arrived(0));
-    println!("The car door opened: {:?}", car_door_opened());
+    println!("{report}");
+    Ok(())
+}
+
+/// The most cars `replay` will build a bank for. A larger car id in a log is
+/// almost certainly corrupt, and each car gets its own controller.
+const MAX_REPLAY_CARS: usize = 64;
+
+/// Runs `replay FILE [SCHEDULER]`, feeding a recorded event log back into a
+/// bank of controllers and printing the commands they send.
+fn run_replay(args: &[String]) -> Result<(), String> {
+    let path = args.first().ok_or("usage: replay FILE [SCHEDULER]")?;
+    let file = std::fs::File::open(path).map_err(|e| format!("{path}: {e}"))?;
+    let entries = read_log(std::io::BufReader::new(file))
+        .map_err(|e| format!("{path}: {e}"))?;
+    let name = args.get(1).map_or("look", String::as_str);
+    // Size the bank and shaft to cover everything the log mentions.
+    let (mut cars, mut bottom, mut top) = (1, 0, 0);
+    for entry in &entries {
+        let (car, floor) = match entry.event {
+            Event::CarArrived(car, floor)
+            | Event::CarFloorButtonPressed(car, floor) => (Some(car), Some(floor)),
+            Event::CarDoorOpened(car)
+            | Event::CarDoorClosed(car)
+            | Event::CarModeSelected(car, _) => (Some(car), None),
+            Event::LobbyCallButtonPressed(floor, _) => (None, Some(floor)),
+            Event::FireRecallActivated | Event::FireRecallReset => (None, None),
+        };
+        if let Some(car) = car {
+            let count = car.checked_add(1).filter(|&n| n <= MAX_REPLAY_CARS).ok_or_else(|| {
+                format!("{path}: car {car} at {} ms is beyond the {MAX_REPLAY_CARS}-car limit", entry.time)
+            })?;
+            cars = cars.max(count);
+        }
+        bottom = bottom.min(floor.unwrap_or(bottom));
+        top = top.max(floor.unwrap_or(top));
+    }
+    let building = Building::new(bottom, top).map_err(|e| e.to_string())?;
+    let mut controllers = Vec::new();
+    for car in 0..cars {
+        let scheduler = scheduler_by_name(name, building.bottom(), building.top())
+            .ok_or_else(|| format!("unknown scheduler {name:?}"))?;
+        controllers.push(ElevatorController::with_scheduler(
+            car,
+            building.bottom(),
+            scheduler,
+        ));
+    }
+    let mut group = GroupDispatcher::new(
+        building,
+        controllers,
+        Box::new(WeightedCost::default()),
+    );
+    for (time, car, command) in replay(&entries, &mut group) {
+        println!("{time} car {car}: {command:?}");
+    }
+    Ok(())
+}
+
+/// Runs `check FILE [BOTTOM TOP [MAX_WAIT_MS]]`, printing every safety
+/// violation in a recorded event log.
+fn run_check(args: &[String]) -> Result<(), String> {
+    let path = args.first().ok_or("usage: check FILE [BOTTOM TOP [MAX_WAIT_MS]]")?;
+    let building = Building::new(
+        optional_arg(args, 1, "BOTTOM", 0)?,
+        optional_arg(args, 2, "TOP", 9)?,
+    )
+    .map_err(|e| e.to_string())?;
+    let rules = SafetyRules {
+        start_floor: building.bottom(),
+        building,
+        max_wait: optional_arg(args, 3, "MAX_WAIT_MS", 120_000)?,
+    };
+    let file = std::fs::File::open(path).map_err(|e| format!("{path}: {e}"))?;
+    let entries = read_log(std::io::BufReader::new(file))
+        .map_err(|e| format!("{path}: {e}"))?;
+    let violations = check_trace(&entries, &rules);
+    for violation in &violations {
+        println!("{violation}");
+    }
+    if !violations.is_empty() {
+        return Err(format!("{path}: {} safety violation(s)", violations.len()));
+    }
+    println!("{path}: {} events, no violations", entries.len());
+    Ok(())
+}
+
+/// Parses `args[index]` as a `T`, or returns `default` when it is missing.
+/// Values that do not fit in `T` are errors rather than being truncated.
+fn optional_arg<T: std::str::FromStr>(
+    args: &[String],
+    index: usize,
+    name: &str,
+    default: T,
+) -> Result<T, String>
+where
+    T::Err: std::fmt::Display,
+{
+    match args.get(index) {
+        None => Ok(default),
+        Some(arg) => arg.parse().map_err(|e| format!("bad {name} {arg:?}: {e}")),
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let run = match args.first().map(String::as_str) {
+        Some("simulate") => run_simulation,
+        Some("replay") => run_replay,
+        Some("check") => run_check,
+        _ => {
+            demo(args.first());
+            return;
+        }
+    };
+    if let Err(e) = run(&args[1..]) {
+        eprintln!("{e}");
+        std::process::exit(2);
+    }
+}
+
+/// Walks a single car and then a bank of four through a few scripted events.
+fn demo(scheduler: Option<&String>) {
+    let mut controller = match scheduler {
+        None => ElevatorController::new(0, 0),
+        Some(name) => {
+            let Some(scheduler) = scheduler_by_name(name, 0, 9) else {
+                eprintln!(
+                    "Unknown scheduler {name:?}, expected fcfs, sstf, look or scan"
+                );
+                std::process::exit(2);
+            };
+            ElevatorController::with_scheduler(0, 0, scheduler)
+        }
+    };
+    println!("Scheduling with {}", controller.scheduler().name());
+    let events = [
+        lobby_call_button_pressed(0, Direction::Up),
+        car_door_opened(0),
+        car_floor_button_pressed(0, 3),
+        car_door_closed(0),
+        car_arrived(0, 1),
+        car_arrived(0, 2),
+        car_arrived(0, 3),
+        car_door_opened(0),
+        lobby_call_button_pressed(1, Direction::Down),
+        car_door_closed(0),
+        car_arrived(0, 2),
+        car_arrived(0, 1),
+    ];
+    for event in events {
+        println!("{:?} -> {:?}", event, controller.handle(event.clone()));
+    }
     println!(
-        "A passenger has pressed the 3rd floor button: {:?}",
-        car_floor_button_pressed(3)
+        "The car is on floor {} ({:?}, doors {:?}), pending requests: {:?}",
+        controller.floor(),
+        controller.moving(),
+        controller.door(),
+        controller.pending()
     );
-    println!("The car door closed: {:?}", car_door_closed());
-    println!("The car has arrived on the 3rd floor: {:?}", car_arrived(3));
+
+    let mut building = Building::new(-2, 9).expect("valid floor range");
+    for (floor, name) in [(-2, "B2"), (-1, "B1"), (0, "L"), (1, "M")] {
+        building.name_floor(floor, name).expect("unique floor names");
+    }
+    // Cars 2 and 3 run express to the upper floors.
+    building.add_express_zone(1..=5, &[2, 3]).expect("floors in the building");
+    let events = [
+        building.lobby_call_button_pressed(6, Direction::Down),
+        building.lobby_call_button_pressed(2, Direction::Up),
+        building.car_arrived(0, 1),
+        building.lobby_call_button_pressed(4, Direction::Up),
+        building.lobby_call_button_pressed(-2, Direction::Up),
+        building.lobby_call_button_pressed(9, Direction::Up),
+        building.car_floor_button_pressed(3, 3),
+        building.car_floor_button_pressed(1, 12),
+    ];
+    let lobby = building.floor_by_name("L").expect("the lobby is named");
+    building.set_recall_floor(lobby).expect("the lobby is in the building");
+    let cars = (0..4).map(|car| ElevatorController::new(car, lobby)).collect();
+    let mut group =
+        GroupDispatcher::new(building, cars, Box::new(WeightedCost::default()));
+    for event in events {
+        match event {
+            Ok(event) => {
+                println!("{:?} -> {:?}", event, group.handle(event.clone()))
+            }
+            Err(e) => println!("Rejected: {e}"),
+        }
+    }
+    for car in group.cars() {
+        let floors: Vec<String> =
+            car.pending().iter().map(|&f| group.building().floor_name(f)).collect();
+        println!("Car {} is assigned floors {:?}", car.car(), floors);
+    }
+
+    // A fire alarm sends every car in service back to the lobby, where a
+    // firefighter takes over car 0 and car 3 is shut down for inspection.
+    let events = [
+        car_mode_selected(1, CarMode::IndependentService),
+        fire_recall_activated(),
+        car_mode_selected(0, CarMode::FirefighterService),
+        car_floor_button_pressed(0, 5),
+        car_mode_selected(3, CarMode::OutOfService),
+        fire_recall_reset(),
+        car_mode_selected(0, CarMode::Normal),
+    ];
+    for event in events {
+        println!("{:?} -> {:?}", event, group.handle(event.clone()));
+    }
+    for car in group.cars() {
+        println!("Car {} is in {:?} mode", car.car(), car.mode());
+    }
 }
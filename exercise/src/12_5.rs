@@ -1,122 +1,994 @@
-/// 2 つのサブ式に対して実行する演算。
-#[derive(Debug)]
+mod mod_12_5 {
+    pub mod bigint;
+    pub mod bytecode;
+    pub mod derive;
+    pub mod environment;
+    pub mod fold;
+    pub mod number;
+    pub mod ops;
+    pub mod parser;
+    pub mod rational;
+    pub mod simplify;
+    pub mod types;
+}
+
+use std::fmt;
+use std::time::Instant;
+
+use mod_12_5::bigint::BigInt;
+use mod_12_5::bytecode::compile;
+use mod_12_5::environment::Environment;
+use mod_12_5::fold::{fold, Fold, NodeCount};
+use mod_12_5::number::Number;
+use mod_12_5::ops::expr;
+use mod_12_5::parser::parse;
+use mod_12_5::rational::Rational;
+use mod_12_5::simplify::simplify;
+use mod_12_5::types::{apply, negate, type_of, Scalar, Type};
+use thiserror::Error;
+
+/// 2 つのサブ式に対して実行する演算。比較は真偽値を返す。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum Operation {
     Add,
     Sub,
     Mul,
     Div,
+    Rem,
+    Pow,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
 }
 
-/// ツリー形式の式。
-#[derive(Debug)]
-enum Expression {
+/// ツリー形式の式。値の型 `N` は `Number` を実装する型。
+#[derive(Debug, PartialEq, Eq)]
+enum Expression<N = i64> {
     /// 2 つのサブ式に対する演算。
-    Op { op: Operation, left: Box<Expression>, right: Box<Expression> },
+    Op { op: Operation, left: Box<Expression<N>>, right: Box<Expression<N>> },
 
     /// リテラル値
-    Value(i64),
-}
-
-fn eval(e: Expression) -> Result<i64, String> {
-    match e {
-        Expression::Value(v) => Ok(v),
-        Expression::Op { op, left, right } => {
-            let left_val = eval(*left)?;
-            let right_val = eval(*right)?;
-            match op {
-                Operation::Add => Ok(left_val + right_val),
-                Operation::Sub => Ok(left_val - right_val),
-                Operation::Mul => Ok(left_val * right_val),
-                Operation::Div => {
-                    if right_val == 0 {
-                        Err(String::from("division by zero"))
-                    } else {
-                        Ok(left_val / right_val)
-                    }
+    Value(N),
+
+    /// 真偽値のリテラル。
+    Bool(bool),
+
+    /// 単項マイナス。
+    Neg(Box<Expression<N>>),
+
+    /// 変数の参照。
+    Var(String),
+
+    /// `value` の値を `name` に束縛して `body` を評価する。
+    Let { name: String, value: Box<Expression<N>>, body: Box<Expression<N>> },
+
+    /// `cond` が真なら `then`、偽なら `otherwise` の値になる。
+    If {
+        cond: Box<Expression<N>>,
+        then: Box<Expression<N>>,
+        otherwise: Box<Expression<N>>,
+    },
+}
+
+impl<N> Drop for Expression<N> {
+    /// 深い木を再帰せずに解放する。子を手元のスタックへ取り出してから解放
+    /// するので、自動生成の再帰的な解放に頼らない。
+    fn drop(&mut self) {
+        fn take_children<N>(e: &mut Expression<N>, stack: &mut Vec<Expression<N>>) {
+            let children = match e {
+                Expression::Op { left, right, .. } => vec![left, right],
+                Expression::Let { value, body, .. } => vec![value, body],
+                Expression::Neg(operand) => vec![operand],
+                Expression::If { cond, then, otherwise } => {
+                    vec![cond, then, otherwise]
+                }
+                Expression::Value(_) | Expression::Bool(_) | Expression::Var(_) => {
+                    return
                 }
+            };
+            // 空の名前は割り当てを伴わない。
+            for child in children {
+                stack.push(std::mem::replace(child, Expression::Var(String::new())));
             }
         }
+        let mut stack = Vec::new();
+        take_children(self, &mut stack);
+        while let Some(mut e) = stack.pop() {
+            take_children(&mut e, &mut stack);
+        }
+    }
+}
+
+impl<N: Clone> Clone for Expression<N> {
+    /// `Drop` と同じく、深い木も再帰せずに複製する。
+    fn clone(&self) -> Expression<N> {
+        fold(self, &mut Copier)
+    }
+}
+
+/// 木を同じ形に組み立て直す。
+struct Copier;
+
+impl<N: Clone> Fold<N> for Copier {
+    type Output = Expression<N>;
+
+    fn value(&mut self, v: &N) -> Expression<N> {
+        Expression::Value(v.clone())
+    }
+
+    fn boolean(&mut self, b: bool) -> Expression<N> {
+        Expression::Bool(b)
+    }
+
+    fn var(&mut self, name: &str) -> Expression<N> {
+        Expression::Var(name.to_owned())
+    }
+
+    fn op(
+        &mut self,
+        op: Operation,
+        left: Expression<N>,
+        right: Expression<N>,
+    ) -> Expression<N> {
+        Expression::Op { op, left: Box::new(left), right: Box::new(right) }
+    }
+
+    fn neg(&mut self, operand: Expression<N>) -> Expression<N> {
+        Expression::Neg(Box::new(operand))
+    }
+
+    fn let_in(
+        &mut self,
+        name: &str,
+        value: Expression<N>,
+        body: Expression<N>,
+    ) -> Expression<N> {
+        Expression::Let {
+            name: name.to_owned(),
+            value: Box::new(value),
+            body: Box::new(body),
+        }
+    }
+
+    fn if_else(
+        &mut self,
+        cond: Expression<N>,
+        then: Expression<N>,
+        otherwise: Expression<N>,
+    ) -> Expression<N> {
+        Expression::If {
+            cond: Box::new(cond),
+            then: Box::new(then),
+            otherwise: Box::new(otherwise),
+        }
+    }
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let symbol = match self {
+            Operation::Add => "+",
+            Operation::Sub => "-",
+            Operation::Mul => "*",
+            Operation::Div => "/",
+            Operation::Rem => "%",
+            Operation::Pow => "^",
+            Operation::Eq => "==",
+            Operation::Ne => "!=",
+            Operation::Lt => "<",
+            Operation::Le => "<=",
+            Operation::Gt => ">",
+            Operation::Ge => ">=",
+        };
+        f.write_str(symbol)
+    }
+}
+
+impl Operation {
+    /// 結合の強さ。大きいほど強く結びつく。
+    fn precedence(self) -> u8 {
+        match self {
+            Operation::Add | Operation::Sub => SUM,
+            Operation::Mul | Operation::Div | Operation::Rem => PRODUCT,
+            Operation::Pow => POWER,
+            _ => COMPARISON,
+        }
+    }
+
+    /// 真偽値を返す比較か。
+    fn is_comparison(self) -> bool {
+        self.precedence() == COMPARISON
+    }
+}
+
+/// `parse` で読み戻せる中置記法で表示する。括弧は、優先順位と結合の向きの
+/// ために必要なところにだけ付ける。
+impl<N: Number> fmt::Display for Expression<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&fold(self, &mut Printer).0)
+    }
+}
+
+/// 式を文字列にする。出力は文字列と、その式の結合の強さの組。`let` と `if` は
+/// 最後の部分ができるだけ右へ伸びるので最も弱く、値と変数は最も強い。ただし
+/// `-3` のような負の値は単項マイナスと同じ強さとして扱う。有理数の `7/2` の
+/// ように `/` を含む値は、1 つのリテラルとして読み戻せるように `(7/2)` と書く。
+struct Printer;
+
+const LET: u8 = 0;
+const COMPARISON: u8 = 1;
+const SUM: u8 = 2;
+const PRODUCT: u8 = 3;
+const UNARY: u8 = 4;
+const POWER: u8 = 5;
+const ATOM: u8 = 6;
+
+fn parenthesize(text: String, needed: bool) -> String {
+    if needed {
+        format!("({text})")
+    } else {
+        text
+    }
+}
+
+impl<N: Number> Fold<N> for Printer {
+    type Output = (String, u8);
+
+    fn value(&mut self, v: &N) -> (String, u8) {
+        let text = v.to_string();
+        if text.contains('/') {
+            (format!("({text})"), ATOM)
+        } else if text.starts_with('-') {
+            (text, UNARY)
+        } else {
+            (text, ATOM)
+        }
+    }
+
+    fn boolean(&mut self, b: bool) -> (String, u8) {
+        (b.to_string(), ATOM)
+    }
+
+    fn var(&mut self, name: &str) -> (String, u8) {
+        (name.to_owned(), ATOM)
+    }
+
+    fn op(
+        &mut self,
+        op: Operation,
+        left: (String, u8),
+        right: (String, u8),
+    ) -> (String, u8) {
+        let p = op.precedence();
+        let (left_needs, right_needs) = match op {
+            // `^` は右結合で、右辺には単項マイナスも書ける。
+            Operation::Pow => (left.1 <= p, right.1 < UNARY),
+            // 比較は連ねられないので、両辺とも同じ強さなら括弧が要る。
+            _ if op.is_comparison() => (left.1 <= p, right.1 <= p),
+            // 左結合なので、右側は同じ強さでも括弧が要る。
+            _ => (left.1 < p, right.1 <= p),
+        };
+        let left = parenthesize(left.0, left_needs);
+        let right = parenthesize(right.0, right_needs);
+        (format!("{left} {op} {right}"), p)
+    }
+
+    fn neg(&mut self, operand: (String, u8)) -> (String, u8) {
+        // `-3` と書くと負のリテラルとして読まれるので、`-(3)` にする。
+        let literal =
+            operand.1 == ATOM && operand.0.starts_with(|c: char| c.is_ascii_digit());
+        let needed = operand.1 < UNARY || literal;
+        (format!("-{}", parenthesize(operand.0, needed)), UNARY)
+    }
+
+    fn let_in(
+        &mut self,
+        name: &str,
+        value: (String, u8),
+        body: (String, u8),
+    ) -> (String, u8) {
+        (format!("let {name} = {} in {}", value.0, body.0), LET)
+    }
+
+    fn if_else(
+        &mut self,
+        cond: (String, u8),
+        then: (String, u8),
+        otherwise: (String, u8),
+    ) -> (String, u8) {
+        (format!("if {} then {} else {}", cond.0, then.0, otherwise.0), LET)
+    }
+}
+
+/// 式の評価に失敗した理由。
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+enum EvalError {
+    #[error("division by zero")]
+    DivisionByZero,
+    #[error("arithmetic overflow")]
+    Overflow,
+    #[error("unbound variable \"{0}\"")]
+    UnboundVariable(String),
+    #[error("type mismatch: expected {expected}, found {found}")]
+    TypeMismatch { expected: Type, found: Type },
+    #[error("invalid exponent")]
+    InvalidExponent,
+}
+
+/// 木をそのまま評価する。`e` は消費しないので、同じ式を何度でも評価できる。
+/// 値が数でなければ `TypeMismatch`。
+fn eval<N: Number>(e: &Expression<N>, env: &Environment<N>) -> Result<N, EvalError> {
+    eval_scalar(e, env)?.number()
+}
+
+/// `eval` と同じだが、真偽値の結果も返す。
+fn eval_scalar<N: Number>(
+    e: &Expression<N>,
+    env: &Environment<N>,
+) -> Result<Scalar<N>, EvalError> {
+    fold(e, &mut Evaluator { env, scope: Vec::new(), skip: false, ifs: Vec::new() })
+}
+
+/// `eval` の本体。エラーは値として根まで運び、左の子のエラーを優先する。
+/// `if` の選ばれなかった枝は、`skip` を立てて計算せずに通り過ぎる。
+struct Evaluator<'a, N> {
+    env: &'a Environment<N>,
+    /// 今見えている `let` の束縛。内側のものほど後ろにある。
+    scope: Vec<(String, Result<Scalar<N>, EvalError>)>,
+    /// 選ばれなかった枝の中にいる。値は使われないので、何も計算しない。
+    skip: bool,
+    /// 畳み込み中の `if` ごとに、外側の `skip` と選んだ枝 (`true` なら `then`)。
+    /// 条件がエラーか真偽値でなければ、どちらの枝も選ばない。
+    ifs: Vec<(bool, Option<bool>)>,
+}
+
+impl<N> Evaluator<'_, N> {
+    /// 通り過ぎる部分の値。使われないので何でもよい。
+    fn skipped() -> Result<Scalar<N>, EvalError> {
+        Ok(Scalar::Bool(false))
+    }
+}
+
+impl<N: Number> Fold<N> for Evaluator<'_, N> {
+    type Output = Result<Scalar<N>, EvalError>;
+
+    fn value(&mut self, v: &N) -> Self::Output {
+        if self.skip {
+            return Self::skipped();
+        }
+        Ok(Scalar::Number(v.clone()))
+    }
+
+    fn boolean(&mut self, b: bool) -> Self::Output {
+        Ok(Scalar::Bool(b))
+    }
+
+    fn var(&mut self, name: &str) -> Self::Output {
+        if self.skip {
+            return Self::skipped();
+        }
+        match self.scope.iter().rev().find(|(n, _)| n == name) {
+            Some((_, value)) => value.clone(),
+            None => self
+                .env
+                .get(name)
+                .map(Scalar::Number)
+                .ok_or(EvalError::UnboundVariable(name.to_owned())),
+        }
+    }
+
+    fn op(
+        &mut self,
+        op: Operation,
+        left: Self::Output,
+        right: Self::Output,
+    ) -> Self::Output {
+        if self.skip {
+            return Self::skipped();
+        }
+        apply(op, &left?, &right?)
+    }
+
+    fn neg(&mut self, operand: Self::Output) -> Self::Output {
+        if self.skip {
+            return Self::skipped();
+        }
+        negate(&operand?)
+    }
+
+    fn bind(&mut self, name: &str, value: &Self::Output) {
+        // 通り過ぎる間も、`let_in` で外せるように積んでおく。
+        let value = if self.skip { Self::skipped() } else { value.clone() };
+        self.scope.push((name.to_owned(), value));
+    }
+
+    fn let_in(
+        &mut self,
+        _: &str,
+        value: Self::Output,
+        body: Self::Output,
+    ) -> Self::Output {
+        self.scope.pop();
+        value?;
+        body
+    }
+
+    fn enter_then(&mut self, cond: &Self::Output) {
+        let taken = match cond {
+            _ if self.skip => None,
+            Ok(Scalar::Bool(b)) => Some(*b),
+            _ => None,
+        };
+        self.ifs.push((self.skip, taken));
+        self.skip = taken != Some(true);
+    }
+
+    fn enter_else(&mut self, _: &Self::Output) {
+        let &(outer, taken) =
+            self.ifs.last().expect("enter_else without enter_then");
+        self.skip = outer || taken != Some(false);
+    }
+
+    fn if_else(
+        &mut self,
+        cond: Self::Output,
+        then: Self::Output,
+        otherwise: Self::Output,
+    ) -> Self::Output {
+        let (outer, _) = self.ifs.pop().expect("if_else without enter_then");
+        self.skip = outer;
+        if self.skip {
+            return Self::skipped();
+        }
+        if cond?.bool()? {
+            then
+        } else {
+            otherwise
+        }
     }
 }
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.is_empty() {
+        let formula =
+            "let gross = price * quantity * (2 - 1) in gross - gross / (5 * 2)";
+        println!("{formula}");
+        // 同じ式を何度も評価するので、先に簡約してコンパイルしておく。
+        let e = simplify(&parse(formula).expect("the example formula is valid"));
+        println!("  simplified: {e}");
+        let program = compile(&e);
+        for (price, quantity) in [(120, 3), (45, 10), (7, 0)] {
+            let env = Environment::from([("price", price), ("quantity", quantity)]);
+            println!("  price={price} quantity={quantity}: {:?}", program.run(&env));
+        }
+
+        // 同じ式を、数の型を変えて評価する。
+        for input in ["7 / 2", "0.1 * 3 - 0.3", "9223372036854775807 * 4 / 3"] {
+            println!("{input}");
+            show::<i64>("i64", input, Environment::new());
+            show::<f64>("f64", input, Environment::default());
+            show::<Rational>("rational", input, Environment::default());
+            show::<BigInt>("bigint", input, Environment::default());
+        }
+        let price = Rational::new(1999, 100).expect("a valid price");
+        let env = Environment::from([
+            ("price", price),
+            ("quantity", Rational::new(3, 1).unwrap()),
+        ]);
+        show("rational", "price * quantity * (1 - 0.15)", env);
+
+        // 比較と if を含む規則。型は評価する前に調べられる。
+        let rule =
+            "let factor = if age >= 65 then 2 else 1 in income * factor >= 30000";
+        let e = parse(rule).expect("the example rule is valid");
+        println!("{rule}");
+        match type_of(&e) {
+            Ok(t) => println!("  type: {t}"),
+            Err(err) => println!("  type: {err}"),
+        }
+        for (age, income) in [(30, 45000), (70, 20000), (40, 12000)] {
+            let env = Environment::from([("age", age), ("income", income)]);
+            println!("  age={age} income={income}: {:?}", eval_scalar(&e, &env));
+        }
+        for input in ["if age then 1 else 0", "age + (age > 18)"] {
+            let e = parse(input).expect("the example is valid syntax");
+            match type_of(&e) {
+                Ok(t) => println!("{input}: {t}"),
+                Err(err) => println!("{input}: {err}"),
+            }
+        }
+
+        // 勾配は差分ではなく記号微分で正確に求める。
+        let loss = "let r = w * x - y in r * r / 2 + w ^ 2 / 10";
+        let e: Expression<f64> = loss.parse().expect("the example loss is valid");
+        let env = Environment::from([("w", 0.5), ("x", 3.0), ("y", 2.0)]);
+        println!("{loss}");
+        match e.derive("w") {
+            Ok(d) => {
+                println!("  d/dw = {d}\n       = {:?} at w=0.5", eval(&d, &env))
+            }
+            Err(err) => println!("  d/dw: {err}"),
+        }
+
+        // 組み込みの規則は、文字列を介さずに組み立てられる。
+        let fee: Expression = expr!((price - discount) * rate / 100 + 2);
+        let env =
+            Environment::from([("price", 1200), ("discount", 200), ("rate", 3)]);
+        println!("{fee}\n  price=1200 discount=200 rate=3: {:?}", eval(&fee, &env));
+        return;
+    }
+    if args[0] == "bench" {
+        let records = match args.get(1).map(|n| n.parse()) {
+            None => 1_000_000,
+            Some(Ok(n)) => n,
+            Some(Err(e)) => {
+                eprintln!("Invalid record count {:?}: {e}", args[1]);
+                std::process::exit(2);
+            }
+        };
+        bench(records);
+        return;
+    }
+    for input in args {
+        match parse(&input) {
+            Ok(e) => match eval_scalar(&e, &Environment::new()) {
+                Ok(value) => println!("{input} = {value}"),
+                Err(err) => println!("{input}: {err}"),
+            },
+            Err(err) => println!("{input}: {err}"),
+        }
+    }
+}
+
+/// `input` を `N` の式として読み、`env` の下で評価して表示する。
+fn show<N: Number>(label: &str, input: &str, env: Environment<N>) {
+    match input.parse::<Expression<N>>() {
+        Ok(e) => match eval(&e, &env) {
+            Ok(value) => println!("  {label:>8}: {value}"),
+            Err(err) => println!("  {label:>8}: {err}"),
+        },
+        Err(err) => println!("  {label:>8}: {err}"),
+    }
+}
+
+/// 同じ式を `records` 件の入力に対して評価し、木の巡回とバイトコードの
+/// 実行にかかった時間を比べる。
+fn bench(records: i64) {
+    let formula =
+        "let a = x * 3 + y in let b = a * a - x / (y + 1) in (b + a) * 2 - (x - y)";
+    let e = parse(formula).expect("the benchmark formula is valid");
+    let program = compile(&e);
+    println!("{formula}");
+    println!(
+        "{} nodes, {} instructions, inputs {:?}, {records} records",
+        fold(&e, &mut NodeCount),
+        program.code().len(),
+        program.inputs()
+    );
+
+    let mut env = Environment::new();
+    let mut run =
+        |name: &str, eval: &dyn Fn(&Environment) -> Result<i64, EvalError>| {
+            let start = Instant::now();
+            let mut checksum = 0i64;
+            for i in 0..records {
+                env.set("x", i % 1000);
+                env.set("y", i % 7);
+                checksum = checksum
+                    .wrapping_add(eval(&env).expect("no errors in the benchmark"));
+            }
+            let elapsed = start.elapsed();
+            println!(
+                "{name:>10}: {elapsed:>10.2?} ({:.1} ns/eval), checksum {checksum}",
+                elapsed.as_nanos() as f64 / records.max(1) as f64
+            );
+        };
+    run("tree", &|env| eval(&e, env));
+    run("bytecode", &|env| program.run(env));
+}
+
 #[test]
 fn test_value() {
-    assert_eq!(eval(Expression::Value(19)), Ok(19));
+    assert_eq!(eval(&Expression::Value(19), &Environment::new()), Ok(19));
 }
 
 #[test]
 fn test_sum() {
+    assert_eq!(eval(&expr!(10 + 20), &Environment::new()), Ok(30));
+}
+
+#[test]
+fn test_recursion() {
+    let term1 = expr!(10 * 9);
+    let term2 = expr!((3 - 4) * 5);
+    assert_eq!(eval(&(term1 + term2), &Environment::new()), Ok(85));
+}
+
+#[test]
+fn test_zeros() {
+    assert_eq!(eval(&expr!(0 + 0), &Environment::new()), Ok(0));
+    assert_eq!(eval(&expr!(0 * 0), &Environment::new()), Ok(0));
+    assert_eq!(eval(&expr!(0 - 0), &Environment::new()), Ok(0));
+}
+
+#[test]
+fn test_error() {
     assert_eq!(
-        eval(Expression::Op {
-            op: Operation::Add,
-            left: Box::new(Expression::Value(10)),
-            right: Box::new(Expression::Value(20)),
-        }),
-        Ok(30)
+        eval(&expr!(99 / 0), &Environment::new()),
+        Err(EvalError::DivisionByZero)
     );
 }
 
 #[test]
-fn test_recursion() {
-    let term1 = Expression::Op {
-        op: Operation::Mul,
-        left: Box::new(Expression::Value(10)),
-        right: Box::new(Expression::Value(9)),
-    };
-    let term2 = Expression::Op {
-        op: Operation::Mul,
-        left: Box::new(Expression::Op {
-            op: Operation::Sub,
-            left: Box::new(Expression::Value(3)),
-            right: Box::new(Expression::Value(4)),
-        }),
-        right: Box::new(Expression::Value(5)),
-    };
+fn test_parse() {
+    let env = Environment::new();
+    assert_eq!(eval(&parse("(3 - 4) * 5 + 10 * 9").unwrap(), &env), Ok(85));
+    assert_eq!(eval(&parse("-3 * -3").unwrap(), &env), Ok(9));
     assert_eq!(
-        eval(Expression::Op {
-            op: Operation::Add,
-            left: Box::new(term1),
-            right: Box::new(term2),
-        }),
-        Ok(85)
+        eval(&parse("1 / (2 - 2)").unwrap(), &env),
+        Err(EvalError::DivisionByZero)
     );
 }
 
 #[test]
-fn test_zeros() {
+fn test_overflow() {
+    let env = Environment::new();
+    for input in [
+        "9223372036854775807 + 1",
+        "-9223372036854775808 - 1",
+        "4611686018427387904 * 2",
+        "-9223372036854775808 / -1",
+    ] {
+        assert_eq!(eval(&parse(input).unwrap(), &env), Err(EvalError::Overflow));
+    }
     assert_eq!(
-        eval(Expression::Op {
-            op: Operation::Add,
-            left: Box::new(Expression::Value(0)),
-            right: Box::new(Expression::Value(0))
-        }),
-        Ok(0)
+        eval(&parse("-9223372036854775807 - 1").unwrap(), &env),
+        Ok(i64::MIN)
     );
+}
+
+#[test]
+fn test_variables() {
+    let env = Environment::from([("x", 3), ("y", 4)]);
+    assert_eq!(eval(&parse("x * x + y * y").unwrap(), &env), Ok(25));
+    assert_eq!(
+        eval(&parse("x + z").unwrap(), &env),
+        Err(EvalError::UnboundVariable(String::from("z")))
+    );
+    // 内側の let は外側の束縛を隠すが、本体の外には影響しない。
     assert_eq!(
-        eval(Expression::Op {
-            op: Operation::Mul,
-            left: Box::new(Expression::Value(0)),
-            right: Box::new(Expression::Value(0))
-        }),
-        Ok(0)
+        eval(&parse("(let x = 10 in let x = x + 1 in x * y) + x").unwrap(), &env),
+        Ok(47)
     );
     assert_eq!(
-        eval(Expression::Op {
-            op: Operation::Sub,
-            left: Box::new(Expression::Value(0)),
-            right: Box::new(Expression::Value(0))
-        }),
-        Ok(0)
+        eval(&parse("let a = 1 in b").unwrap(), &Environment::new()),
+        Err(EvalError::UnboundVariable(String::from("b")))
     );
 }
 
 #[test]
-fn test_error() {
+fn test_deep_tree() {
+    // 10 万段の左に偏った木でもスタックを使い果たさない。
+    let mut e = Expression::Value(0);
+    for i in 1..=100_000 {
+        e = e + Expression::from(i % 3);
+    }
+    let env = Environment::new();
+    assert_eq!(eval(&e, &env), Ok(100_000));
+    // 消費しないので、同じ木をもう一度評価できる。
+    assert_eq!(eval(&e, &env), Ok(100_000));
+    assert_eq!(simplify(&e), Expression::Value(100_000));
+    assert_eq!(compile(&e).run(&env), Ok(100_000));
+}
+
+#[test]
+fn test_display() {
+    for (input, printed) in [
+        ("(3 - 4) * 5 + 10 * 9", "(3 - 4) * 5 + 10 * 9"),
+        ("((1 + 2)) + (3 + 4)", "1 + 2 + (3 + 4)"),
+        ("8 - (4 - 2)", "8 - (4 - 2)"),
+        ("(8 / 4) / 2 * x", "8 / 4 / 2 * x"),
+        ("a * (b / c)", "a * (b / c)"),
+        ("-x - -3", "-x - -3"),
+        ("0 - x", "0 - x"),
+        ("-(3)", "-(3)"),
+        ("(-3) ^ 2", "(-3) ^ 2"),
+        ("-3 ^ 2", "-3 ^ 2"),
+        ("(2 ^ 3) ^ 2", "(2 ^ 3) ^ 2"),
+        ("2 ^ (3 ^ 2)", "2 ^ 3 ^ 2"),
+        ("2 ^ (-x)", "2 ^ -x"),
+        ("-(x * y)", "-(x * y)"),
+        ("x % (y * 2)", "x % (y * 2)"),
+        ("(a < b) == (c > d)", "(a < b) == (c > d)"),
+        ("a + 1 <= b * 2", "a + 1 <= b * 2"),
+        ("(if a then b else c) + 1", "(if a then b else c) + 1"),
+        (
+            "if (if a then b else c) then x else y",
+            "if if a then b else c then x else y",
+        ),
+        (
+            "(let x = 1 in x) * (let y = 2 in y)",
+            "(let x = 1 in x) * (let y = 2 in y)",
+        ),
+        ("1 + (let x = 2 in x + 3)", "1 + (let x = 2 in x + 3)"),
+        ("let x = (let y = 1 in y) in x", "let x = let y = 1 in y in x"),
+    ] {
+        assert_eq!(parse(input).unwrap().to_string(), printed, "{input}");
+    }
+}
+
+#[test]
+fn test_print_parse_round_trip() {
+    /// 再現できるように種を固定した xorshift 乱数。
+    fn next(state: &mut u64) -> u64 {
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        *state
+    }
+
+    /// 数の型ごとに、端の値と小さな値を混ぜて作る。
+    fn random_integer(state: &mut u64) -> i64 {
+        match next(state) % 4 {
+            0 => i64::MIN,
+            1 => i64::MAX,
+            _ => (next(state) % 201) as i64 - 100,
+        }
+    }
+
+    fn random_rational(state: &mut u64) -> Rational {
+        let den = match next(state) % 4 {
+            0 => i64::MAX,
+            _ => (next(state) % 12) as i64 + 1,
+        };
+        Rational::new(random_integer(state), den).unwrap()
+    }
+
+    fn random_float(state: &mut u64) -> f64 {
+        match f64::from_bits(next(state)) {
+            x if x.is_finite() && next(state).is_multiple_of(2) => x,
+            _ => random_integer(state) as f64 / 8.0,
+        }
+    }
+
+    fn random_expression<N>(
+        state: &mut u64,
+        depth: u32,
+        value: fn(&mut u64) -> N,
+    ) -> Expression<N> {
+        let choice = if depth == 0 { next(state) % 3 } else { next(state) % 10 };
+        match choice {
+            0 => Expression::Value(value(state)),
+            1 => Expression::Var(
+                ["x", "y", "rate_2", "_tmp"][next(state) as usize % 4].to_owned(),
+            ),
+            2 => Expression::Bool(next(state).is_multiple_of(2)),
+            3 => Expression::Let {
+                name: ["x", "y"][next(state) as usize % 2].to_owned(),
+                value: Box::new(random_expression(state, depth - 1, value)),
+                body: Box::new(random_expression(state, depth - 1, value)),
+            },
+            4 => {
+                Expression::Neg(Box::new(random_expression(state, depth - 1, value)))
+            }
+            5 => Expression::If {
+                cond: Box::new(random_expression(state, depth - 1, value)),
+                then: Box::new(random_expression(state, depth - 1, value)),
+                otherwise: Box::new(random_expression(state, depth - 1, value)),
+            },
+            _ => Expression::Op {
+                op: [
+                    Operation::Add,
+                    Operation::Sub,
+                    Operation::Mul,
+                    Operation::Div,
+                    Operation::Rem,
+                    Operation::Pow,
+                    Operation::Eq,
+                    Operation::Ne,
+                    Operation::Lt,
+                    Operation::Le,
+                    Operation::Gt,
+                    Operation::Ge,
+                ][next(state) as usize % 12],
+                left: Box::new(random_expression(state, depth - 1, value)),
+                right: Box::new(random_expression(state, depth - 1, value)),
+            },
+        }
+    }
+
+    fn check<N: Number + PartialEq>(value: fn(&mut u64) -> N) {
+        let mut state = 0x2545_f491_4f6c_dd1d;
+        for _ in 0..2000 {
+            let e = random_expression(&mut state, 6, value);
+            let printed = e.to_string();
+            assert_eq!(printed.parse(), Ok(e), "{printed}");
+        }
+    }
+
+    check(random_integer);
+    check(random_rational);
+    check(random_float);
+}
+
+#[test]
+fn test_number_types() {
+    fn value<N: Number>(input: &str) -> Result<N, EvalError> {
+        let e: Expression<N> = input.parse().unwrap();
+        let env = Environment::default();
+        let result = eval(&e, &env);
+        assert_eq!(compile(&e).run(&env), result, "{input}");
+        assert_eq!(eval(&simplify(&e), &env), result, "{input}");
+        result
+    }
+
+    assert_eq!(value::<i64>("7 / 2"), Ok(3));
+    assert_eq!(value::<f64>("7 / 2"), Ok(3.5));
+    assert_eq!(value::<Rational>("7 / 2"), Ok(Rational::new(7, 2).unwrap()));
+    assert_eq!(value::<BigInt>("7 / 2"), Ok(BigInt::from(3)));
+
+    assert_eq!(value::<Rational>("0.1 * 3 - 0.3"), Ok(Rational::zero()));
+    assert_eq!(value::<f64>("1 / (0.5 - 0.5)"), Err(EvalError::DivisionByZero));
+    assert_eq!(value::<Rational>("1 / (0.5 - 0.5)"), Err(EvalError::DivisionByZero));
+    assert_eq!(value::<BigInt>("1 / (2 - 2)"), Err(EvalError::DivisionByZero));
+    assert_eq!(
+        value::<BigInt>("9223372036854775807 * 4 / 3").map(|v| v.to_string()),
+        Ok(String::from("12297829382473034409"))
+    );
+
+    assert_eq!(value::<i64>("-7 % 3 + 2 ^ 10"), Ok(1023));
+    assert_eq!(value::<f64>("7.5 % 2 + 2 ^ -1"), Ok(2.0));
+    assert_eq!(
+        value::<Rational>("2 ^ -2 + 3.5 % 1"),
+        Ok(Rational::new(3, 4).unwrap())
+    );
+    assert_eq!(
+        value::<BigInt>("-2 ^ 64 % 1000").map(|v| v.to_string()),
+        Ok(String::from("-616"))
+    );
+    assert_eq!(value::<i64>("2 ^ -1"), Err(EvalError::InvalidExponent));
+    assert_eq!(value::<Rational>("4 ^ 0.5"), Err(EvalError::InvalidExponent));
+    assert_eq!(value::<f64>("if 0.1 + 0.2 == 0.3 then 1 else 0"), Ok(0.0));
+    assert_eq!(
+        value::<Rational>("if 0.1 + 0.2 == 0.3 then 1 else 0"),
+        Ok(Rational::one())
+    );
+}
+
+#[test]
+fn test_booleans_and_if() {
+    let env = Environment::from([("x", -4), ("y", 9)]);
+    let scalar = |input: &str| eval_scalar(&parse(input).unwrap(), &env);
+    assert_eq!(scalar("x < y"), Ok(Scalar::Bool(true)));
+    assert_eq!(scalar("(x >= y) == false"), Ok(Scalar::Bool(true)));
+    assert_eq!(scalar("if x < 0 then -x else x"), Ok(Scalar::Number(4)));
+    assert_eq!(
+        scalar("let pos = y > 0 in if pos then y % 4 else 0"),
+        Ok(Scalar::Number(1))
+    );
+    // 選ばれなかった枝は評価しないので、そのエラーも現れない。
+    assert_eq!(scalar("if x == 0 then 1 / x else y / x"), Ok(Scalar::Number(-2)));
+    assert_eq!(scalar("if false then missing else 0"), Ok(Scalar::Number(0)));
+    assert_eq!(scalar("if 1 / 0 > 1 then x else y"), Err(EvalError::DivisionByZero));
+}
+
+#[test]
+fn test_if_skips_unused_branch() {
+    use std::cell::Cell;
+
+    thread_local! {
+        static OPERATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    /// 演算の回数を数える整数。選ばれなかった枝を計算すると数が増える。
+    #[derive(Debug, Clone, PartialEq, PartialOrd)]
+    struct Counted(i64);
+
+    fn count(result: Result<i64, EvalError>) -> Result<Counted, EvalError> {
+        OPERATIONS.with(|n| n.set(n.get() + 1));
+        result.map(Counted)
+    }
+
+    impl fmt::Display for Counted {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            self.0.fmt(f)
+        }
+    }
+
+    impl Number for Counted {
+        fn zero() -> Counted {
+            Counted(0)
+        }
+
+        fn one() -> Counted {
+            Counted(1)
+        }
+
+        fn from_literal(text: &str) -> Option<Counted> {
+            i64::from_literal(text).map(Counted)
+        }
+
+        fn try_add(&self, rhs: &Counted) -> Result<Counted, EvalError> {
+            count(self.0.try_add(&rhs.0))
+        }
+
+        fn try_sub(&self, rhs: &Counted) -> Result<Counted, EvalError> {
+            count(self.0.try_sub(&rhs.0))
+        }
+
+        fn try_mul(&self, rhs: &Counted) -> Result<Counted, EvalError> {
+            count(self.0.try_mul(&rhs.0))
+        }
+
+        fn try_div(&self, rhs: &Counted) -> Result<Counted, EvalError> {
+            count(self.0.try_div(&rhs.0))
+        }
+
+        fn try_rem(&self, rhs: &Counted) -> Result<Counted, EvalError> {
+            count(self.0.try_rem(&rhs.0))
+        }
+
+        fn try_pow(&self, exp: &Counted) -> Result<Counted, EvalError> {
+            count(self.0.try_pow(&exp.0))
+        }
+    }
+
+    let env = Environment::from([("x", Counted(0))]);
+    for (input, value, operations) in [
+        ("if true then 1 else 1 / x", 1, 0),
+        ("if x == 0 then x + 1 else x * x * x", 1, 1),
+        (
+            "if x != 0 then 1 / x else let y = x + 2 in if y > 1 then y else 1 / x",
+            2,
+            1,
+        ),
+        ("if x == 0 then (if false then x % x else -x) else x ^ 2 - 1", 0, 1),
+    ] {
+        OPERATIONS.with(|n| n.set(0));
+        let e: Expression<Counted> = input.parse().unwrap();
+        assert_eq!(eval(&e, &env), Ok(Counted(value)), "{input}");
+        assert_eq!(OPERATIONS.with(Cell::get), operations, "{input}");
+    }
+}
+
+#[test]
+fn test_type_errors() {
+    let env = Environment::from([("x", 3)]);
+    let mismatch = |expected, found| EvalError::TypeMismatch { expected, found };
+    for (input, error) in [
+        ("x < 1", mismatch(Type::Number, Type::Bool)),
+        ("(x < 1) + 1", mismatch(Type::Number, Type::Bool)),
+        ("-(x == 3)", mismatch(Type::Number, Type::Bool)),
+        ("if x then 1 else 2", mismatch(Type::Bool, Type::Number)),
+        ("true == 1", mismatch(Type::Bool, Type::Number)),
+        ("true < false", mismatch(Type::Number, Type::Bool)),
+        ("let b = x > 2 in b * 2", mismatch(Type::Number, Type::Bool)),
+    ] {
+        let e = parse(input).unwrap();
+        assert_eq!(eval(&e, &env), Err(error.clone()), "{input}");
+        assert_eq!(compile(&e).run(&env), Err(error.clone()), "{input}");
+        assert_eq!(eval(&simplify(&e), &env), Err(error), "{input}");
+    }
     assert_eq!(
-        eval(Expression::Op {
-            op: Operation::Div,
-            left: Box::new(Expression::Value(99)),
-            right: Box::new(Expression::Value(0)),
-        }),
-        Err(String::from("division by zero"))
+        eval(&parse("if x then 1 else 2").unwrap(), &env).unwrap_err().to_string(),
+        "type mismatch: expected boolean, found number"
     );
 }
+
+#[test]
+fn test_rational_display_keeps_value() {
+    // 7/2 は括弧で囲んだ 1 つのリテラルとして表示し、読み戻すと同じ木になる。
+    let value = |num, den| Expression::Value(Rational::new(num, den).unwrap());
+    for (e, printed) in [
+        (Expression::Var(String::from("x")) / value(7, 2), "x / (7/2)"),
+        (value(-7, 2) * value(2, 1), "(-7/2) * 2"),
+        (-value(1, 3) + value(-5, 1), "-(1/3) + -5"),
+    ] {
+        assert_eq!(e.to_string(), printed);
+        assert_eq!(printed.parse(), Ok(e), "{printed}");
+    }
+}
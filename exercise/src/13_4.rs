@@ -1,32 +1,238 @@
+mod mod_13_4 {
+    pub mod background;
+    pub mod combinators;
+    pub mod file;
+    pub mod gzip;
+    pub mod json;
+    #[cfg(test)]
+    pub mod memory;
+    pub mod record;
+    pub mod target;
+    pub mod throttle;
+}
+
+use std::io;
+use std::sync::Arc;
+
+use mod_13_4::background::{AsyncLogger, Overflow};
+use mod_13_4::combinators::{Prefix, Tee, Timestamp};
+use mod_13_4::file::{FileLogger, Rotation};
+use mod_13_4::json::JsonLinesLogger;
+use mod_13_4::record::{Record, Value};
+use mod_13_4::target::{Spec, TargetFilter};
+use mod_13_4::throttle::{Rate, Throttle};
+
 pub trait Logger {
-  /// 指定された詳細度レベルでメッセージをログに記録します。
-  fn log(&self, verbosity: u8, message: &str);
+    /// 指定された詳細度レベルでメッセージをログに記録します。
+    fn log(&self, verbosity: u8, message: &str);
+
+    /// 付加情報の付いた記録を残します。既定では `message key=value ...` の
+    /// 形のテキストにして `log` に渡すので、付加情報を扱えるロガーだけが
+    /// 上書きします。ほかのロガーを包むロガーは、付加情報が失われないように
+    /// これも中身へ渡します。
+    fn log_record(&self, record: &Record) {
+        self.log(record.verbosity, &record.to_string());
+    }
+
+    /// 付加情報をキーと値の組で渡して記録します。
+    fn log_kv(&self, verbosity: u8, message: &str, fields: &[(&str, Value)]) {
+        self.log_target("", verbosity, message, fields);
+    }
+
+    /// 記録した部品の名前 `target` を付けて記録します。`TargetFilter` は
+    /// これを見て部品ごとに詳細度を絞ります。
+    fn log_target(
+        &self,
+        target: &str,
+        verbosity: u8,
+        message: &str,
+        fields: &[(&str, Value)],
+    ) {
+        self.log_record(&Record { target, verbosity, prefix: "", message, fields });
+    }
+}
+
+/// `Box<dyn Logger>` のような所有するポインタも、中身と同じように記録します。
+impl<L: Logger + ?Sized> Logger for Box<L> {
+    fn log(&self, verbosity: u8, message: &str) {
+        (**self).log(verbosity, message);
+    }
+
+    fn log_record(&self, record: &Record) {
+        (**self).log_record(record);
+    }
+}
+
+/// 共有された `Arc<dyn Logger>` も、中身と同じように記録します。
+impl<L: Logger + ?Sized> Logger for Arc<L> {
+    fn log(&self, verbosity: u8, message: &str) {
+        (**self).log(verbosity, message);
+    }
+
+    fn log_record(&self, record: &Record) {
+        (**self).log_record(record);
+    }
 }
 
 struct StdoutLogger;
 
 impl Logger for StdoutLogger {
-  fn log(&self, verbosity: u8, message: &str) {
-      println!("verbosity={verbosity}: {message}");
-  }
+    fn log(&self, verbosity: u8, message: &str) {
+        println!("verbosity={verbosity}: {message}");
+    }
 }
 
-// TODO: `VerbosityFilter` を定義して実装します。
-struct VerbosityFilter {
+/// `max_verbosity` 以下の詳細度のメッセージだけを `inner` に渡します。
+struct VerbosityFilter<L> {
     max_verbosity: u8,
-    inner: StdoutLogger,
+    inner: L,
 }
 
-impl Logger for VerbosityFilter {
+impl<L: Logger> Logger for VerbosityFilter<L> {
     fn log(&self, verbosity: u8, message: &str) {
         if verbosity <= self.max_verbosity {
             self.inner.log(verbosity, message);
         }
     }
+
+    fn log_record(&self, record: &Record) {
+        if record.verbosity <= self.max_verbosity {
+            self.inner.log_record(record);
+        }
+    }
 }
 
 fn main() {
-  let logger = VerbosityFilter { max_verbosity: 3, inner: StdoutLogger };
-  logger.log(5, "FYI");
-  logger.log(2, "Uhoh");
+    let logger = VerbosityFilter { max_verbosity: 3, inner: StdoutLogger };
+    logger.log(5, "FYI");
+    logger.log(2, "Uhoh");
+
+    // 小さな部品を組み合わせたパイプライン。標準出力は共有し、通信部品の
+    // ログには詳細度 3 まで、時刻付きの監査ログには詳細度 1 までを流します。
+    let stdout: Arc<dyn Logger> = Arc::new(StdoutLogger);
+    let pipeline = Tee::new(vec![
+        Box::new(VerbosityFilter {
+            max_verbosity: 3,
+            inner: Prefix::new("net", stdout.clone()),
+        }) as Box<dyn Logger>,
+        Box::new(Timestamp::new(VerbosityFilter {
+            max_verbosity: 1,
+            inner: stdout,
+        })),
+    ]);
+    pipeline.log(1, "connection lost");
+    pipeline.log(3, "retrying in 5s");
+    pipeline.log(4, "socket buffer drained");
+
+    // 取り込み側が正規表現で解析しなくて済むように、付加情報を JSON Lines で
+    // 書き出します。テキストのロガーには `key=value` の形で渡ります。
+    let json = VerbosityFilter {
+        max_verbosity: 2,
+        inner: Prefix::new("http", JsonLinesLogger::new(io::stdout())),
+    };
+    let path = String::from("/users/42");
+    let fields = [
+        ("method", Value::from("GET")),
+        ("path", Value::from(&path)),
+        ("status", Value::from(200i64)),
+        ("ms", Value::from(12.5)),
+    ];
+    json.log_kv(2, "request \"done\"", &fields);
+    json.log_kv(3, "response body", &[("bytes", Value::from(512u64))]);
+    Timestamp::new(StdoutLogger).log_kv(2, "request done", &fields);
+
+    // 標準出力を使えないデーモンは、日ごとか 1 MiB ごとに切り替わるファイルに
+    // 書きます。古いファイルは圧縮して 5 つまで残します。
+    let path = std::env::temp_dir().join("13_4.log");
+    let rotation = Rotation {
+        max_size: Some(1 << 20),
+        daily: true,
+        backups: 5,
+        compress: true,
+    };
+    match FileLogger::open(&path, rotation) {
+        Ok(file) => {
+            // 時刻は記録した時点で付け、ファイルへの書き込みと切り替えは別の
+            // スレッドに任せます。
+            let logger =
+                Timestamp::new(AsyncLogger::new(file, 1024, Overflow::DropOldest));
+            logger.log_kv(
+                1,
+                "daemon started",
+                &[("pid", Value::from(u64::from(std::process::id())))],
+            );
+            println!("logged to {}", path.display());
+        }
+        Err(e) => eprintln!("cannot open {}: {e}", path.display()),
+    }
+
+    // 出力が追いつかないときの振る舞いを比べます。どれだけ捨てられるかは
+    // ワーカーの速さによります。
+    for overflow in [Overflow::Block, Overflow::DropNewest, Overflow::DropOldest] {
+        let logger = AsyncLogger::new(
+            Prefix::new(&format!("{overflow:?}"), StdoutLogger),
+            2,
+            overflow,
+        );
+        for i in 0..5 {
+            logger.log(2, &format!("burst {i}"));
+        }
+        logger.flush();
+        println!("{overflow:?}: dropped {}", logger.dropped());
+    }
+    // `LOG_SPEC` で部品ごとの詳細度を決めます。通信部品だけを詳しく見たい
+    // ときも、ほかの部品のログはあふれません。
+    let spec = std::env::var("LOG_SPEC")
+        .unwrap_or_else(|_| String::from("2,net=5,db::pool=1"));
+    match spec.parse::<Spec>() {
+        Ok(spec) => {
+            let logger = TargetFilter::new(spec, StdoutLogger);
+            logger.log(2, "starting");
+            logger.log_target(
+                "net::tcp",
+                5,
+                "sent 512 bytes",
+                &[("peer", Value::from("10.0.0.7"))],
+            );
+            logger.log_target("db::pool", 2, "connection checked out", &[]);
+            logger.log_target("db::query", 2, "select took 3ms", &[]);
+        }
+        Err(e) => eprintln!("invalid LOG_SPEC \"{spec}\": {e}"),
+    }
+    if let Err(e) = "3,net=loud".parse::<Spec>() {
+        println!("{e}");
+    }
+    // エラーが続けざまに起きても、同じ行はまとめ、詳細度ごとに 1 秒に 2 行
+    // (続けてなら 3 行) までしか書きません。
+    let logger = Throttle::new(StdoutLogger, Rate { per_second: 2.0, burst: 3.0 });
+    for _ in 0..100 {
+        logger.log(1, "disk full");
+    }
+    logger.log(1, "disk space recovered");
+    for i in 0..10 {
+        logger.log(3, &format!("flushing segment {i}"));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mod_13_4::memory::Memory;
+
+    #[test]
+    fn filter_wraps_any_logger() {
+        let memory = Arc::new(Memory::default());
+        let boxed: Box<dyn Logger> = Box::new(memory.clone());
+        let filter = VerbosityFilter { max_verbosity: 2, inner: boxed };
+        filter.log(1, "kept");
+        filter.log(3, "dropped");
+        let shared: Arc<dyn Logger> = memory.clone();
+        let nested = VerbosityFilter {
+            max_verbosity: 5,
+            inner: VerbosityFilter { max_verbosity: 4, inner: shared },
+        };
+        nested.log(4, "also kept");
+        nested.log(5, "dropped by the inner filter");
+        assert_eq!(memory.take(), ["1 kept", "4 also kept"]);
+    }
 }
@@ -0,0 +1,292 @@
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
+
+use thiserror::Error;
+
+use crate::{CarId, Direction, Event};
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum BuildingError {
+    #[error("bottom floor {bottom} is above top floor {top}")]
+    EmptyRange { bottom: i32, top: i32 },
+    #[error("floor {floor} is outside the building ({bottom} to {top})")]
+    FloorOutOfRange { floor: i32, bottom: i32, top: i32 },
+    #[error("there is no {dir} call button on floor {floor}")]
+    NoSuchDirection { floor: i32, dir: Direction },
+    #[error("car {car} does not stop at floor {floor}")]
+    NotServed { car: CarId, floor: i32 },
+    #[error("floor name \"{0}\" is already taken")]
+    DuplicateName(String),
+    #[error("unknown floor \"{0}\"")]
+    UnknownFloor(String),
+}
+
+/// A range of floors that some cars pass through without stopping.
+#[derive(Debug, Clone)]
+pub struct ExpressZone {
+    pub floors: RangeInclusive<i32>,
+    pub skipped_by: Vec<CarId>,
+}
+
+/// The floors of a building and which cars may stop where.
+#[derive(Debug, Clone)]
+pub struct Building {
+    bottom: i32,
+    top: i32,
+    names: BTreeMap<i32, String>,
+    express_zones: Vec<ExpressZone>,
+    recall_floor: i32,
+}
+
+impl Building {
+    /// A building with floors `bottom..=top`, where floors below 0 are
+    /// basements. Cars are recalled to floor 0 in a fire, or to the nearest
+    /// floor to it if the building has no floor 0.
+    pub fn new(bottom: i32, top: i32) -> Result<Building, BuildingError> {
+        if bottom > top {
+            return Err(BuildingError::EmptyRange { bottom, top });
+        }
+        Ok(Building {
+            bottom,
+            top,
+            names: BTreeMap::new(),
+            express_zones: Vec::new(),
+            recall_floor: 0.clamp(bottom, top),
+        })
+    }
+
+    pub fn bottom(&self) -> i32 {
+        self.bottom
+    }
+
+    pub fn top(&self) -> i32 {
+        self.top
+    }
+
+    pub fn contains(&self, floor: i32) -> bool {
+        (self.bottom..=self.top).contains(&floor)
+    }
+
+    /// Gives `floor` a name such as "L" or "B2" to show instead of its number.
+    pub fn name_floor(
+        &mut self,
+        floor: i32,
+        name: &str,
+    ) -> Result<(), BuildingError> {
+        self.check_floor(floor)?;
+        if self.names.iter().any(|(&f, n)| f != floor && n == name) {
+            return Err(BuildingError::DuplicateName(name.to_owned()));
+        }
+        self.names.insert(floor, name.to_owned());
+        Ok(())
+    }
+
+    /// The name of `floor`, or its number if it has none.
+    pub fn floor_name(&self, floor: i32) -> String {
+        self.names.get(&floor).cloned().unwrap_or_else(|| floor.to_string())
+    }
+
+    /// Looks up a floor by name or by number.
+    pub fn floor_by_name(&self, name: &str) -> Result<i32, BuildingError> {
+        let named = self.names.iter().find(|(_, n)| *n == name).map(|(&f, _)| f);
+        let floor = named
+            .or_else(|| name.parse().ok())
+            .ok_or_else(|| BuildingError::UnknownFloor(name.to_owned()))?;
+        self.check_floor(floor)?;
+        Ok(floor)
+    }
+
+    /// The floor cars return to on fire recall.
+    pub fn recall_floor(&self) -> i32 {
+        self.recall_floor
+    }
+
+    pub fn set_recall_floor(&mut self, floor: i32) -> Result<(), BuildingError> {
+        self.check_floor(floor)?;
+        self.recall_floor = floor;
+        Ok(())
+    }
+
+    /// Declares that the cars in `skipped_by` do not stop at any of `floors`.
+    pub fn add_express_zone(
+        &mut self,
+        floors: RangeInclusive<i32>,
+        skipped_by: &[CarId],
+    ) -> Result<(), BuildingError> {
+        if floors.is_empty() {
+            return Err(BuildingError::EmptyRange {
+                bottom: *floors.start(),
+                top: *floors.end(),
+            });
+        }
+        self.check_floor(*floors.start())?;
+        self.check_floor(*floors.end())?;
+        self.express_zones
+            .push(ExpressZone { floors, skipped_by: skipped_by.to_vec() });
+        Ok(())
+    }
+
+    /// Whether `car` can stop at `floor`.
+    pub fn serves(&self, car: CarId, floor: i32) -> bool {
+        self.contains(floor)
+            && !self.express_zones.iter().any(|zone| {
+                zone.floors.contains(&floor) && zone.skipped_by.contains(&car)
+            })
+    }
+
+    fn check_floor(&self, floor: i32) -> Result<(), BuildingError> {
+        if self.contains(floor) {
+            Ok(())
+        } else {
+            Err(BuildingError::FloorOutOfRange {
+                floor,
+                bottom: self.bottom,
+                top: self.top,
+            })
+        }
+    }
+
+    /// Like `car_arrived`, but only for floors inside the building.
+    pub fn car_arrived(
+        &self,
+        car: CarId,
+        floor: i32,
+    ) -> Result<Event, BuildingError> {
+        self.check_floor(floor)?;
+        Ok(crate::car_arrived(car, floor))
+    }
+
+    /// Like `lobby_call_button_pressed`, but rejects floors outside the
+    /// building and directions that lead out of it.
+    pub fn lobby_call_button_pressed(
+        &self,
+        floor: i32,
+        dir: Direction,
+    ) -> Result<Event, BuildingError> {
+        self.check_floor(floor)?;
+        let end = match dir {
+            Direction::Up => self.top,
+            Direction::Down => self.bottom,
+        };
+        if floor == end {
+            return Err(BuildingError::NoSuchDirection { floor, dir });
+        }
+        Ok(crate::lobby_call_button_pressed(floor, dir))
+    }
+
+    /// Like `car_floor_button_pressed`, but only for floors the car stops at.
+    pub fn car_floor_button_pressed(
+        &self,
+        car: CarId,
+        floor: i32,
+    ) -> Result<Event, BuildingError> {
+        self.check_floor(floor)?;
+        if !self.serves(car, floor) {
+            return Err(BuildingError::NotServed { car, floor });
+        }
+        Ok(crate::car_floor_button_pressed(car, floor))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn tower() -> Building {
+        let mut building = Building::new(-2, 30).unwrap();
+        building.name_floor(-2, "B2").unwrap();
+        building.name_floor(-1, "B1").unwrap();
+        building.name_floor(0, "L").unwrap();
+        building.name_floor(1, "M").unwrap();
+        // Cars 2 and 3 run express from the lobby to the upper floors.
+        building.add_express_zone(1..=19, &[2, 3]).unwrap();
+        building
+    }
+
+    #[test]
+    fn range() {
+        assert_eq!(
+            Building::new(3, 2).unwrap_err(),
+            BuildingError::EmptyRange { bottom: 3, top: 2 }
+        );
+        let building = tower();
+        assert!(building.contains(-2));
+        assert!(building.contains(30));
+        assert!(!building.contains(31));
+    }
+
+    #[test]
+    fn names() {
+        let mut building = tower();
+        assert_eq!(building.floor_name(-2), "B2");
+        assert_eq!(building.floor_name(7), "7");
+        assert_eq!(building.floor_by_name("L"), Ok(0));
+        assert_eq!(building.floor_by_name("12"), Ok(12));
+        assert_eq!(
+            building.floor_by_name("P"),
+            Err(BuildingError::UnknownFloor(String::from("P")))
+        );
+        assert!(building.floor_by_name("40").is_err());
+        assert_eq!(
+            building.name_floor(2, "M"),
+            Err(BuildingError::DuplicateName(String::from("M")))
+        );
+        // Renaming a floor is fine.
+        building.name_floor(1, "Mezzanine").unwrap();
+        assert_eq!(building.floor_name(1), "Mezzanine");
+    }
+
+    #[test]
+    fn recall_floor() {
+        let mut building = tower();
+        assert_eq!(building.recall_floor(), 0);
+        assert_eq!(Building::new(3, 9).unwrap().recall_floor(), 3);
+        building.set_recall_floor(1).unwrap();
+        assert_eq!(building.recall_floor(), 1);
+        assert!(building.set_recall_floor(-3).is_err());
+    }
+
+    #[test]
+    fn express_zones() {
+        let building = tower();
+        assert!(building.serves(0, 10));
+        assert!(!building.serves(2, 10));
+        assert!(building.serves(2, 0));
+        assert!(building.serves(3, 20));
+        assert!(!building.serves(0, 31));
+        assert!(tower().add_express_zone(25..=40, &[1]).is_err());
+        assert_eq!(
+            tower().add_express_zone(RangeInclusive::new(19, 1), &[2]).unwrap_err(),
+            BuildingError::EmptyRange { bottom: 19, top: 1 }
+        );
+    }
+
+    #[test]
+    fn validated_events() {
+        let building = Building::new(0, 1).unwrap();
+        assert_eq!(
+            building.car_floor_button_pressed(0, 3),
+            Err(BuildingError::FloorOutOfRange { floor: 3, bottom: 0, top: 1 })
+        );
+        assert_eq!(
+            building.lobby_call_button_pressed(1, Direction::Up),
+            Err(BuildingError::NoSuchDirection { floor: 1, dir: Direction::Up })
+        );
+        assert_eq!(
+            building
+                .lobby_call_button_pressed(0, Direction::Down)
+                .unwrap_err()
+                .to_string(),
+            "there is no down call button on floor 0"
+        );
+        assert_eq!(
+            building.lobby_call_button_pressed(0, Direction::Up),
+            Ok(crate::lobby_call_button_pressed(0, Direction::Up))
+        );
+        assert!(building.car_arrived(0, -1).is_err());
+        assert_eq!(
+            tower().car_floor_button_pressed(2, 5),
+            Err(BuildingError::NotServed { car: 2, floor: 5 })
+        );
+    }
+}
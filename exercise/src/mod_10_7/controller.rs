@@ -0,0 +1,624 @@
+use super::scheduler::{Look, Scheduler};
+use crate::{CarId, CarMode, Direction, Event};
+
+/// A command the controller sends to the car hardware.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    MoveUp,
+    MoveDown,
+    Stop,
+    OpenDoor,
+    CloseDoor,
+}
+
+/// Whether the car doors are open or closed. The controller considers the
+/// doors to be opening or closing from the moment it sends the command until
+/// the car reports that they have finished.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DoorState {
+    Open,
+    Closed,
+    Opening,
+    Closing,
+}
+
+/// The rules a car is currently operating under.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperatingMode {
+    /// Serves lobby calls and car calls.
+    Normal,
+    /// Fire recall phase I: returns to the recall floor, parks with the doors
+    /// open and ignores every call.
+    FireRecall,
+    /// Fire recall phase II: serves one car call at a time, ignores lobby
+    /// calls and only closes the doors when a car button is pressed.
+    FirefighterService,
+    /// Serves car calls only, and only closes the doors when a car button is
+    /// pressed.
+    IndependentService,
+    /// Ignores every call and stops at the next floor if moving.
+    OutOfService,
+}
+
+/// Reacts to a stream of `Event`s and decides what one car should do next.
+pub struct ElevatorController {
+    car: CarId,
+    mode: OperatingMode,
+    fire_recall: bool,
+    recall_floor: i32,
+    floor: i32,
+    door: DoorState,
+    moving: Option<Direction>,
+    heading: Direction,
+    pending: Vec<i32>,
+    scheduler: Box<dyn Scheduler>,
+}
+
+impl ElevatorController {
+    /// Creates a controller for car `car`, idle with closed doors on `floor`
+    /// and scheduled with `Look`. `floor` is also the fire recall floor until
+    /// `set_recall_floor` says otherwise.
+    pub fn new(car: CarId, floor: i32) -> ElevatorController {
+        ElevatorController::with_scheduler(car, floor, Box::new(Look))
+    }
+
+    /// Like `new`, but with the given scheduling strategy.
+    pub fn with_scheduler(
+        car: CarId,
+        floor: i32,
+        scheduler: Box<dyn Scheduler>,
+    ) -> ElevatorController {
+        ElevatorController {
+            car,
+            mode: OperatingMode::Normal,
+            fire_recall: false,
+            recall_floor: floor,
+            floor,
+            door: DoorState::Closed,
+            moving: None,
+            heading: Direction::Up,
+            pending: Vec::new(),
+            scheduler,
+        }
+    }
+
+    /// The scheduling strategy in use.
+    pub fn scheduler(&self) -> &dyn Scheduler {
+        self.scheduler.as_ref()
+    }
+
+    /// The car this controller drives.
+    pub fn car(&self) -> CarId {
+        self.car
+    }
+
+    /// The rules the car is currently operating under.
+    pub fn mode(&self) -> OperatingMode {
+        self.mode
+    }
+
+    /// Sets the floor the car returns to on fire recall.
+    pub fn set_recall_floor(&mut self, floor: i32) {
+        self.recall_floor = floor;
+    }
+
+    /// The floor the car was last seen on.
+    pub fn floor(&self) -> i32 {
+        self.floor
+    }
+
+    /// The current state of the car doors.
+    pub fn door(&self) -> DoorState {
+        self.door
+    }
+
+    /// The direction the car is currently travelling in, if any.
+    pub fn moving(&self) -> Option<Direction> {
+        self.moving
+    }
+
+    /// Floors that have been requested but not yet served, oldest first.
+    pub fn pending(&self) -> &[i32] {
+        &self.pending
+    }
+
+    /// Updates the controller state for `event` and returns the commands to
+    /// send to the car, in order. Events for other cars are ignored; lobby
+    /// calls are taken to be assigned to this car.
+    pub fn handle(&mut self, event: Event) -> Vec<Command> {
+        match event {
+            Event::CarArrived(car, _)
+            | Event::CarDoorOpened(car)
+            | Event::CarDoorClosed(car)
+            | Event::CarFloorButtonPressed(car, _)
+            | Event::CarModeSelected(car, _)
+                if car != self.car =>
+            {
+                Vec::new()
+            }
+            Event::CarArrived(_, floor) => self.arrived(floor),
+            Event::CarDoorOpened(_) => {
+                self.door = DoorState::Open;
+                let auto_close = matches!(
+                    self.mode,
+                    OperatingMode::Normal | OperatingMode::FireRecall
+                );
+                if self.pending.is_empty() || !auto_close {
+                    Vec::new()
+                } else {
+                    self.door = DoorState::Closing;
+                    vec![Command::CloseDoor]
+                }
+            }
+            Event::CarDoorClosed(_) => {
+                self.door = DoorState::Closed;
+                self.depart().into_iter().collect()
+            }
+            Event::LobbyCallButtonPressed(floor, _) => match self.mode {
+                OperatingMode::Normal => self.request(floor),
+                _ => Vec::new(),
+            },
+            Event::CarFloorButtonPressed(_, floor) => match self.mode {
+                OperatingMode::Normal | OperatingMode::IndependentService => {
+                    self.request(floor)
+                }
+                OperatingMode::FirefighterService => {
+                    // A new destination replaces the previous one.
+                    self.pending.clear();
+                    self.request(floor)
+                }
+                OperatingMode::FireRecall | OperatingMode::OutOfService => {
+                    Vec::new()
+                }
+            },
+            Event::FireRecallActivated => {
+                self.fire_recall = true;
+                match self.mode {
+                    OperatingMode::Normal | OperatingMode::IndependentService => {
+                        self.recall()
+                    }
+                    _ => Vec::new(),
+                }
+            }
+            Event::FireRecallReset => {
+                self.fire_recall = false;
+                if self.mode == OperatingMode::FireRecall {
+                    self.mode = OperatingMode::Normal;
+                }
+                Vec::new()
+            }
+            Event::CarModeSelected(_, mode) => self.select(mode),
+        }
+    }
+
+    /// Sends the car to the recall floor, dropping every other request.
+    fn recall(&mut self) -> Vec<Command> {
+        self.mode = OperatingMode::FireRecall;
+        self.pending.clear();
+        self.request(self.recall_floor)
+    }
+
+    fn select(&mut self, mode: CarMode) -> Vec<Command> {
+        match (mode, self.mode) {
+            (CarMode::Normal, OperatingMode::FireRecall) => Vec::new(),
+            // Whatever the car was doing, it may only return to service by
+            // being recalled while phase I is active.
+            (CarMode::Normal, _) if self.fire_recall => self.recall(),
+            (CarMode::Normal, _) => {
+                self.mode = OperatingMode::Normal;
+                Vec::new()
+            }
+            // Phase II is only available once phase I has recalled the car.
+            (CarMode::FirefighterService, OperatingMode::FireRecall) => {
+                self.mode = OperatingMode::FirefighterService;
+                self.pending.clear();
+                Vec::new()
+            }
+            (CarMode::IndependentService, OperatingMode::Normal) => {
+                self.mode = OperatingMode::IndependentService;
+                Vec::new()
+            }
+            (CarMode::OutOfService, _) => {
+                self.mode = OperatingMode::OutOfService;
+                self.pending.clear();
+                Vec::new()
+            }
+            (CarMode::FirefighterService | CarMode::IndependentService, _) => {
+                Vec::new()
+            }
+        }
+    }
+
+    fn arrived(&mut self, floor: i32) -> Vec<Command> {
+        self.floor = floor;
+        let next = self.scheduler.next(&self.pending, floor, self.heading);
+        if next == Some(floor) && self.pending.contains(&floor) {
+            self.pending.retain(|&f| f != floor);
+            self.moving = None;
+            self.door = DoorState::Opening;
+            return vec![Command::Stop, Command::OpenDoor];
+        }
+        let keep_going = match (self.moving, next) {
+            (Some(Direction::Up), Some(target)) => target > floor,
+            (Some(Direction::Down), Some(target)) => target < floor,
+            _ => false,
+        };
+        if keep_going {
+            return Vec::new();
+        }
+        // Nothing further this way: stop here and let the scheduler turn us
+        // around.
+        let mut commands = vec![Command::Stop];
+        self.moving = None;
+        commands.extend(self.depart());
+        commands
+    }
+
+    fn request(&mut self, floor: i32) -> Vec<Command> {
+        if floor == self.floor && self.moving.is_none() {
+            // Already here: just let the passenger in.
+            if self.door != DoorState::Closed {
+                return Vec::new();
+            }
+            self.door = DoorState::Opening;
+            return vec![Command::OpenDoor];
+        }
+        if !self.pending.contains(&floor) {
+            self.pending.push(floor);
+        }
+        match (self.moving, self.door) {
+            (Some(_), _) => Vec::new(),
+            (None, DoorState::Open) => {
+                self.door = DoorState::Closing;
+                vec![Command::CloseDoor]
+            }
+            (None, DoorState::Closed) => self.depart().into_iter().collect(),
+            // The doors will close once they have opened, or are already
+            // closing.
+            (None, DoorState::Opening | DoorState::Closing) => Vec::new(),
+        }
+    }
+
+    /// Starts the car towards the floor the scheduler picks, if any.
+    fn depart(&mut self) -> Option<Command> {
+        if self.moving.is_some() {
+            return None;
+        }
+        let target = self.scheduler.next(&self.pending, self.floor, self.heading)?;
+        let (direction, command) = if target > self.floor {
+            (Direction::Up, Command::MoveUp)
+        } else if target < self.floor {
+            (Direction::Down, Command::MoveDown)
+        } else {
+            self.pending.retain(|&f| f != target);
+            self.door = DoorState::Opening;
+            return Some(Command::OpenDoor);
+        };
+        self.moving = Some(direction);
+        self.heading = direction;
+        Some(command)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::scheduler::{Fcfs, Scan};
+    use super::*;
+    use crate::{
+        car_arrived, car_door_closed, car_door_opened, car_floor_button_pressed,
+        car_mode_selected, fire_recall_activated, fire_recall_reset,
+        lobby_call_button_pressed,
+    };
+
+    #[test]
+    fn call_on_current_floor_opens_door() {
+        let mut controller = ElevatorController::new(0, 0);
+        assert_eq!(
+            controller.handle(lobby_call_button_pressed(0, Direction::Up)),
+            vec![Command::OpenDoor]
+        );
+        assert_eq!(controller.handle(car_door_opened(0)), vec![]);
+        assert_eq!(controller.door(), DoorState::Open);
+    }
+
+    #[test]
+    fn ride_to_requested_floor() {
+        let mut controller = ElevatorController::new(0, 0);
+        controller.handle(car_door_opened(0));
+        assert_eq!(
+            controller.handle(car_floor_button_pressed(0, 3)),
+            vec![Command::CloseDoor]
+        );
+        assert_eq!(controller.handle(car_door_closed(0)), vec![Command::MoveUp]);
+        assert_eq!(controller.moving(), Some(Direction::Up));
+        assert_eq!(controller.handle(car_arrived(0, 1)), vec![]);
+        assert_eq!(controller.handle(car_arrived(0, 2)), vec![]);
+        assert_eq!(
+            controller.handle(car_arrived(0, 3)),
+            vec![Command::Stop, Command::OpenDoor]
+        );
+        assert_eq!(controller.floor(), 3);
+        assert_eq!(controller.moving(), None);
+        assert!(controller.pending().is_empty());
+    }
+
+    #[test]
+    fn stops_for_requests_on_the_way() {
+        let mut controller = ElevatorController::new(0, 0);
+        assert_eq!(
+            controller.handle(car_floor_button_pressed(0, 5)),
+            vec![Command::MoveUp]
+        );
+        assert_eq!(
+            controller.handle(lobby_call_button_pressed(2, Direction::Up)),
+            vec![]
+        );
+        assert_eq!(controller.handle(car_arrived(0, 1)), vec![]);
+        assert_eq!(
+            controller.handle(car_arrived(0, 2)),
+            vec![Command::Stop, Command::OpenDoor]
+        );
+        assert_eq!(controller.handle(car_door_opened(0)), vec![Command::CloseDoor]);
+        assert_eq!(controller.handle(car_door_closed(0)), vec![Command::MoveUp]);
+        assert_eq!(controller.pending(), &[5]);
+    }
+
+    #[test]
+    fn moves_down_for_lower_floor() {
+        let mut controller = ElevatorController::new(0, 4);
+        assert_eq!(
+            controller.handle(lobby_call_button_pressed(1, Direction::Up)),
+            vec![Command::MoveDown]
+        );
+        assert_eq!(controller.moving(), Some(Direction::Down));
+    }
+
+    #[test]
+    fn duplicate_requests_are_merged() {
+        let mut controller = ElevatorController::new(0, 0);
+        controller.handle(car_floor_button_pressed(0, 3));
+        controller.handle(car_floor_button_pressed(0, 3));
+        controller.handle(lobby_call_button_pressed(3, Direction::Down));
+        assert_eq!(controller.pending(), &[3]);
+    }
+
+    #[test]
+    fn fcfs_skips_floors_on_the_way() {
+        let mut controller =
+            ElevatorController::with_scheduler(0, 0, Box::new(Fcfs));
+        assert_eq!(
+            controller.handle(car_floor_button_pressed(0, 5)),
+            vec![Command::MoveUp]
+        );
+        controller.handle(lobby_call_button_pressed(2, Direction::Up));
+        assert_eq!(controller.handle(car_arrived(0, 2)), vec![]);
+        assert_eq!(
+            controller.handle(car_arrived(0, 5)),
+            vec![Command::Stop, Command::OpenDoor]
+        );
+        assert_eq!(controller.handle(car_door_opened(0)), vec![Command::CloseDoor]);
+        assert_eq!(controller.handle(car_door_closed(0)), vec![Command::MoveDown]);
+    }
+
+    #[test]
+    fn scan_turns_around_at_end_of_shaft() {
+        let mut controller =
+            ElevatorController::with_scheduler(0, 2, Box::new(Scan::new(0, 3)));
+        assert_eq!(
+            controller.handle(car_floor_button_pressed(0, 3)),
+            vec![Command::MoveUp]
+        );
+        controller.handle(car_floor_button_pressed(0, 1));
+        assert_eq!(
+            controller.handle(car_arrived(0, 3)),
+            vec![Command::Stop, Command::OpenDoor]
+        );
+        controller.handle(car_door_opened(0));
+        assert_eq!(controller.handle(car_door_closed(0)), vec![Command::MoveDown]);
+        assert_eq!(controller.handle(car_arrived(0, 2)), vec![]);
+        assert_eq!(
+            controller.handle(car_arrived(0, 1)),
+            vec![Command::Stop, Command::OpenDoor]
+        );
+    }
+
+    #[test]
+    fn look_reverses_without_reaching_the_end() {
+        let mut controller = ElevatorController::new(0, 2);
+        controller.handle(car_floor_button_pressed(0, 4));
+        controller.handle(car_arrived(0, 3));
+        // The request behind us is served once the car turns around at 4.
+        controller.handle(car_floor_button_pressed(0, 0));
+        assert_eq!(
+            controller.handle(car_arrived(0, 4)),
+            vec![Command::Stop, Command::OpenDoor]
+        );
+        controller.handle(car_door_opened(0));
+        assert_eq!(controller.handle(car_door_closed(0)), vec![Command::MoveDown]);
+    }
+
+    #[test]
+    fn ignores_events_for_other_cars() {
+        let mut controller = ElevatorController::new(1, 0);
+        assert_eq!(controller.handle(car_floor_button_pressed(0, 3)), vec![]);
+        assert_eq!(controller.handle(car_arrived(0, 3)), vec![]);
+        assert_eq!(controller.floor(), 0);
+        assert!(controller.pending().is_empty());
+        assert_eq!(
+            controller.handle(car_floor_button_pressed(1, 3)),
+            vec![Command::MoveUp]
+        );
+    }
+
+    #[test]
+    fn doors_finish_opening_before_departure() {
+        let mut controller = ElevatorController::new(0, 0);
+        controller.handle(car_floor_button_pressed(0, 0));
+        assert_eq!(controller.door(), DoorState::Opening);
+        assert_eq!(controller.handle(car_floor_button_pressed(0, 4)), vec![]);
+        assert_eq!(controller.handle(car_door_opened(0)), vec![Command::CloseDoor]);
+        assert_eq!(controller.door(), DoorState::Closing);
+        assert_eq!(controller.handle(car_floor_button_pressed(0, 5)), vec![]);
+        assert_eq!(controller.handle(car_door_closed(0)), vec![Command::MoveUp]);
+    }
+
+    #[test]
+    fn fire_recall_returns_to_recall_floor() {
+        let mut controller = ElevatorController::new(0, 0);
+        controller.handle(car_floor_button_pressed(0, 5));
+        controller.handle(car_arrived(0, 1));
+        controller.handle(car_floor_button_pressed(0, 3));
+        assert_eq!(controller.handle(car_arrived(0, 2)), vec![]);
+        assert_eq!(
+            controller.handle(fire_recall_activated()),
+            vec![],
+            "the car turns around at the next floor"
+        );
+        assert_eq!(controller.mode(), OperatingMode::FireRecall);
+        assert_eq!(controller.pending(), &[0]);
+        assert_eq!(
+            controller.handle(car_arrived(0, 3)),
+            vec![Command::Stop, Command::MoveDown]
+        );
+        assert_eq!(
+            controller.handle(lobby_call_button_pressed(2, Direction::Up)),
+            vec![]
+        );
+        assert_eq!(controller.handle(car_floor_button_pressed(0, 2)), vec![]);
+        controller.handle(car_arrived(0, 2));
+        controller.handle(car_arrived(0, 1));
+        assert_eq!(
+            controller.handle(car_arrived(0, 0)),
+            vec![Command::Stop, Command::OpenDoor]
+        );
+        // Parked with the doors open.
+        assert_eq!(controller.handle(car_door_opened(0)), vec![]);
+        controller.handle(fire_recall_reset());
+        assert_eq!(controller.mode(), OperatingMode::Normal);
+        assert_eq!(
+            controller.handle(car_floor_button_pressed(0, 4)),
+            vec![Command::CloseDoor]
+        );
+    }
+
+    #[test]
+    fn fire_recall_with_doors_open_elsewhere() {
+        let mut controller = ElevatorController::new(0, 6);
+        controller.set_recall_floor(1);
+        controller.handle(car_floor_button_pressed(0, 6));
+        controller.handle(car_door_opened(0));
+        assert_eq!(
+            controller.handle(fire_recall_activated()),
+            vec![Command::CloseDoor]
+        );
+        assert_eq!(controller.handle(car_door_closed(0)), vec![Command::MoveDown]);
+    }
+
+    #[test]
+    fn firefighter_service() {
+        let mut controller = ElevatorController::new(0, 0);
+        // Phase II needs phase I first.
+        controller.handle(car_mode_selected(0, CarMode::FirefighterService));
+        assert_eq!(controller.mode(), OperatingMode::Normal);
+
+        controller.handle(fire_recall_activated());
+        controller.handle(car_door_opened(0));
+        controller.handle(car_mode_selected(0, CarMode::FirefighterService));
+        assert_eq!(controller.mode(), OperatingMode::FirefighterService);
+        assert_eq!(
+            controller.handle(car_floor_button_pressed(0, 7)),
+            vec![Command::CloseDoor]
+        );
+        assert_eq!(controller.handle(car_floor_button_pressed(0, 4)), vec![]);
+        assert_eq!(controller.pending(), &[4]);
+        assert_eq!(
+            controller.handle(lobby_call_button_pressed(2, Direction::Up)),
+            vec![]
+        );
+        assert_eq!(controller.handle(car_door_closed(0)), vec![Command::MoveUp]);
+        for floor in 1..4 {
+            controller.handle(car_arrived(0, floor));
+        }
+        assert_eq!(
+            controller.handle(car_arrived(0, 4)),
+            vec![Command::Stop, Command::OpenDoor]
+        );
+        assert_eq!(controller.handle(car_door_opened(0)), vec![]);
+        // Turning the key off while phase I is still active recalls the car.
+        assert_eq!(
+            controller.handle(car_mode_selected(0, CarMode::Normal)),
+            vec![Command::CloseDoor]
+        );
+        assert_eq!(controller.mode(), OperatingMode::FireRecall);
+    }
+
+    #[test]
+    fn independent_service() {
+        let mut controller = ElevatorController::new(0, 0);
+        controller.handle(car_mode_selected(0, CarMode::IndependentService));
+        assert_eq!(
+            controller.handle(lobby_call_button_pressed(3, Direction::Up)),
+            vec![]
+        );
+        assert!(controller.pending().is_empty());
+        assert_eq!(
+            controller.handle(car_floor_button_pressed(0, 2)),
+            vec![Command::MoveUp]
+        );
+        controller.handle(car_floor_button_pressed(0, 5));
+        controller.handle(car_arrived(0, 1));
+        controller.handle(car_arrived(0, 2));
+        // The doors stay open until the attendant presses a button.
+        assert_eq!(controller.handle(car_door_opened(0)), vec![]);
+        assert_eq!(
+            controller.handle(car_floor_button_pressed(0, 5)),
+            vec![Command::CloseDoor]
+        );
+        // Independent service does not override fire recall.
+        controller.handle(fire_recall_activated());
+        assert_eq!(controller.mode(), OperatingMode::FireRecall);
+    }
+
+    #[test]
+    fn out_of_service() {
+        let mut controller = ElevatorController::new(0, 0);
+        controller.handle(car_floor_button_pressed(0, 5));
+        controller.handle(car_arrived(0, 1));
+        controller.handle(car_mode_selected(0, CarMode::OutOfService));
+        assert!(controller.pending().is_empty());
+        assert_eq!(controller.handle(car_arrived(0, 2)), vec![Command::Stop]);
+        assert_eq!(controller.handle(car_floor_button_pressed(0, 4)), vec![]);
+        assert_eq!(
+            controller.handle(lobby_call_button_pressed(1, Direction::Up)),
+            vec![]
+        );
+        assert_eq!(controller.handle(fire_recall_activated()), vec![]);
+        assert_eq!(controller.mode(), OperatingMode::OutOfService);
+        controller.handle(fire_recall_reset());
+        controller.handle(car_mode_selected(0, CarMode::Normal));
+        assert_eq!(
+            controller.handle(car_floor_button_pressed(0, 4)),
+            vec![Command::MoveUp]
+        );
+    }
+
+    #[test]
+    fn back_in_service_during_fire_recall() {
+        let mut controller = ElevatorController::new(0, 3);
+        controller.set_recall_floor(0);
+        controller.handle(car_mode_selected(0, CarMode::OutOfService));
+        assert_eq!(controller.handle(fire_recall_activated()), vec![]);
+        assert_eq!(
+            controller.handle(car_mode_selected(0, CarMode::Normal)),
+            vec![Command::MoveDown]
+        );
+        assert_eq!(controller.mode(), OperatingMode::FireRecall);
+        assert_eq!(controller.pending(), &[0]);
+        assert_eq!(
+            controller.handle(lobby_call_button_pressed(5, Direction::Down)),
+            vec![]
+        );
+        assert_eq!(controller.handle(car_floor_button_pressed(0, 6)), vec![]);
+        assert_eq!(controller.pending(), &[0]);
+    }
+}
@@ -0,0 +1,380 @@
+//! A line-based text format for recorded events.
+//!
+//! Each line holds a timestamp in milliseconds followed by one event:
+//!
+//! ```text
+//! 1200 lobby-call 3 up
+//! 1500 car-floor-button 0 7
+//! 4000 car-arrived 0 3
+//! 6000 car-door-opened 0
+//! 9000 car-door-closed 0
+//! ```
+//!
+//! Blank lines and lines starting with `#` are ignored.
+
+use std::fmt;
+use std::io::BufRead;
+use std::str::FromStr;
+
+use thiserror::Error;
+
+use super::controller::Command;
+use super::group::GroupDispatcher;
+use crate::{CarId, CarMode, Direction, Event};
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ParseEventError {
+    #[error("missing field: expected {0}")]
+    MissingField(&'static str),
+    #[error("unexpected trailing field \"{0}\"")]
+    TrailingField(String),
+    #[error("unknown event \"{0}\"")]
+    UnknownEvent(String),
+    #[error("invalid direction \"{0}\"")]
+    InvalidDirection(String),
+    #[error("invalid mode \"{0}\"")]
+    InvalidMode(String),
+    #[error("invalid number \"{0}\"")]
+    InvalidNumber(String),
+}
+
+#[derive(Debug, Error)]
+pub enum LogError {
+    #[error("line {line}: {source}")]
+    Parse { line: usize, source: ParseEventError },
+    #[error("timestamps go backwards on line {line}")]
+    OutOfOrder { line: usize },
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Direction::Up => write!(f, "up"),
+            Direction::Down => write!(f, "down"),
+        }
+    }
+}
+
+impl FromStr for Direction {
+    type Err = ParseEventError;
+
+    fn from_str(s: &str) -> Result<Direction, ParseEventError> {
+        match s {
+            "up" => Ok(Direction::Up),
+            "down" => Ok(Direction::Down),
+            _ => Err(ParseEventError::InvalidDirection(s.to_owned())),
+        }
+    }
+}
+
+impl fmt::Display for CarMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CarMode::Normal => write!(f, "normal"),
+            CarMode::FirefighterService => write!(f, "firefighter-service"),
+            CarMode::IndependentService => write!(f, "independent-service"),
+            CarMode::OutOfService => write!(f, "out-of-service"),
+        }
+    }
+}
+
+impl FromStr for CarMode {
+    type Err = ParseEventError;
+
+    fn from_str(s: &str) -> Result<CarMode, ParseEventError> {
+        match s {
+            "normal" => Ok(CarMode::Normal),
+            "firefighter-service" => Ok(CarMode::FirefighterService),
+            "independent-service" => Ok(CarMode::IndependentService),
+            "out-of-service" => Ok(CarMode::OutOfService),
+            _ => Err(ParseEventError::InvalidMode(s.to_owned())),
+        }
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Event::CarArrived(car, floor) => write!(f, "car-arrived {car} {floor}"),
+            Event::CarDoorOpened(car) => write!(f, "car-door-opened {car}"),
+            Event::CarDoorClosed(car) => write!(f, "car-door-closed {car}"),
+            Event::LobbyCallButtonPressed(floor, dir) => {
+                write!(f, "lobby-call {floor} {dir}")
+            }
+            Event::CarFloorButtonPressed(car, floor) => {
+                write!(f, "car-floor-button {car} {floor}")
+            }
+            Event::FireRecallActivated => write!(f, "fire-recall-activated"),
+            Event::FireRecallReset => write!(f, "fire-recall-reset"),
+            Event::CarModeSelected(car, mode) => write!(f, "car-mode {car} {mode}"),
+        }
+    }
+}
+
+/// Splits a line into whitespace-separated fields and parses them one by one.
+struct Fields<'a>(std::str::SplitWhitespace<'a>);
+
+impl<'a> Fields<'a> {
+    fn field(&mut self, name: &'static str) -> Result<&'a str, ParseEventError> {
+        self.0.next().ok_or(ParseEventError::MissingField(name))
+    }
+
+    fn number<T: FromStr>(
+        &mut self,
+        name: &'static str,
+    ) -> Result<T, ParseEventError> {
+        let field = self.field(name)?;
+        field.parse().map_err(|_| ParseEventError::InvalidNumber(field.to_owned()))
+    }
+
+    fn finish(mut self) -> Result<(), ParseEventError> {
+        match self.0.next() {
+            None => Ok(()),
+            Some(field) => Err(ParseEventError::TrailingField(field.to_owned())),
+        }
+    }
+}
+
+impl FromStr for Event {
+    type Err = ParseEventError;
+
+    fn from_str(s: &str) -> Result<Event, ParseEventError> {
+        let mut fields = Fields(s.split_whitespace());
+        let event = match fields.field("event")? {
+            "car-arrived" => {
+                Event::CarArrived(fields.number("car")?, fields.number("floor")?)
+            }
+            "car-door-opened" => Event::CarDoorOpened(fields.number("car")?),
+            "car-door-closed" => Event::CarDoorClosed(fields.number("car")?),
+            "lobby-call" => Event::LobbyCallButtonPressed(
+                fields.number("floor")?,
+                fields.field("direction")?.parse()?,
+            ),
+            "car-floor-button" => Event::CarFloorButtonPressed(
+                fields.number("car")?,
+                fields.number("floor")?,
+            ),
+            "fire-recall-activated" => Event::FireRecallActivated,
+            "fire-recall-reset" => Event::FireRecallReset,
+            "car-mode" => Event::CarModeSelected(
+                fields.number("car")?,
+                fields.field("mode")?.parse()?,
+            ),
+            kind => return Err(ParseEventError::UnknownEvent(kind.to_owned())),
+        };
+        fields.finish()?;
+        Ok(event)
+    }
+}
+
+/// One line of an event log: an event and when it happened, in milliseconds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogEntry {
+    pub time: u64,
+    pub event: Event,
+}
+
+impl fmt::Display for LogEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.time, self.event)
+    }
+}
+
+impl FromStr for LogEntry {
+    type Err = ParseEventError;
+
+    fn from_str(s: &str) -> Result<LogEntry, ParseEventError> {
+        let s = s.trim_start();
+        let (time, event) = s.split_once(char::is_whitespace).unwrap_or((s, ""));
+        let time = Fields(time.split_whitespace()).number("timestamp")?;
+        Ok(LogEntry { time, event: event.parse()? })
+    }
+}
+
+/// Reads a whole event log. Timestamps must not decrease from one entry to
+/// the next.
+pub fn read_log(input: impl BufRead) -> Result<Vec<LogEntry>, LogError> {
+    let mut entries: Vec<LogEntry> = Vec::new();
+    for (index, line) in input.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let entry: LogEntry = line
+            .parse()
+            .map_err(|source| LogError::Parse { line: index + 1, source })?;
+        if entries.last().is_some_and(|last| last.time > entry.time) {
+            return Err(LogError::OutOfOrder { line: index + 1 });
+        }
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// Feeds recorded events through `group` in order and returns every command
+/// it sends, stamped with the time of the event that caused it.
+pub fn replay(
+    entries: &[LogEntry],
+    group: &mut GroupDispatcher,
+) -> Vec<(u64, CarId, Command)> {
+    let mut commands = Vec::new();
+    for entry in entries {
+        for (car, command) in group.handle(entry.event.clone()) {
+            commands.push((entry.time, car, command));
+        }
+    }
+    commands
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::building::Building;
+    use super::super::controller::ElevatorController;
+    use super::super::group::WeightedCost;
+    use super::*;
+    use crate::{
+        car_arrived, car_door_closed, car_door_opened, car_floor_button_pressed,
+        car_mode_selected, fire_recall_activated, fire_recall_reset,
+        lobby_call_button_pressed,
+    };
+
+    #[test]
+    fn round_trip() {
+        let events = [
+            car_arrived(2, -1),
+            car_door_opened(0),
+            car_door_closed(7),
+            lobby_call_button_pressed(3, Direction::Up),
+            lobby_call_button_pressed(-2, Direction::Down),
+            car_floor_button_pressed(1, 12),
+            fire_recall_activated(),
+            fire_recall_reset(),
+            car_mode_selected(3, CarMode::Normal),
+            car_mode_selected(3, CarMode::FirefighterService),
+            car_mode_selected(3, CarMode::IndependentService),
+            car_mode_selected(3, CarMode::OutOfService),
+        ];
+        for (time, event) in events.into_iter().enumerate() {
+            let entry = LogEntry { time: time as u64 * 1000, event };
+            let text = entry.to_string();
+            assert_eq!(text.parse(), Ok(entry), "{text}");
+        }
+    }
+
+    #[test]
+    fn format() {
+        let entry = LogEntry {
+            time: 1200,
+            event: lobby_call_button_pressed(3, Direction::Up),
+        };
+        assert_eq!(entry.to_string(), "1200 lobby-call 3 up");
+        assert_eq!(car_arrived(0, 4).to_string(), "car-arrived 0 4");
+        assert_eq!(
+            car_mode_selected(1, CarMode::OutOfService).to_string(),
+            "car-mode 1 out-of-service"
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(
+            "".parse::<LogEntry>(),
+            Err(ParseEventError::MissingField("timestamp"))
+        );
+        assert_eq!(
+            "12".parse::<LogEntry>(),
+            Err(ParseEventError::MissingField("event"))
+        );
+        assert_eq!(
+            "x car-door-opened 0".parse::<LogEntry>(),
+            Err(ParseEventError::InvalidNumber(String::from("x")))
+        );
+        assert_eq!(
+            "lobby-call 3 sideways".parse::<Event>(),
+            Err(ParseEventError::InvalidDirection(String::from("sideways")))
+        );
+        assert_eq!(
+            "car-arrived 0".parse::<Event>(),
+            Err(ParseEventError::MissingField("floor"))
+        );
+        assert_eq!(
+            "car-door-opened 0 1".parse::<Event>(),
+            Err(ParseEventError::TrailingField(String::from("1")))
+        );
+        assert_eq!(
+            "car-mode 0 party".parse::<Event>(),
+            Err(ParseEventError::InvalidMode(String::from("party")))
+        );
+        assert_eq!(
+            "fire-recall-reset now".parse::<Event>(),
+            Err(ParseEventError::TrailingField(String::from("now")))
+        );
+        assert_eq!(
+            "car-exploded 0".parse::<Event>(),
+            Err(ParseEventError::UnknownEvent(String::from("car-exploded")))
+        );
+    }
+
+    #[test]
+    fn read_whole_log() {
+        let log = "# incident 42\n\n0 lobby-call 3 up\n  500 car-door-opened 0  \n500 car-door-closed 0\n";
+        let entries = read_log(log.as_bytes()).unwrap();
+        assert_eq!(
+            entries,
+            vec![
+                LogEntry {
+                    time: 0,
+                    event: lobby_call_button_pressed(3, Direction::Up)
+                },
+                LogEntry { time: 500, event: car_door_opened(0) },
+                LogEntry { time: 500, event: car_door_closed(0) },
+            ]
+        );
+    }
+
+    #[test]
+    fn read_log_errors() {
+        let err = read_log("0 car-door-opened 0\n1 car-door-shut 0\n".as_bytes())
+            .unwrap_err();
+        assert_eq!(err.to_string(), "line 2: unknown event \"car-door-shut\"");
+        let err = read_log("5 car-door-opened 0\n1 car-door-closed 0\n".as_bytes())
+            .unwrap_err();
+        assert!(matches!(err, LogError::OutOfOrder { line: 2 }));
+    }
+
+    #[test]
+    fn replay_is_deterministic() {
+        let log = "\
+0 lobby-call 5 down
+0 car-floor-button 1 2
+2000 car-arrived 0 1
+4000 car-arrived 1 1
+6000 car-arrived 1 2
+";
+        let entries = read_log(log.as_bytes()).unwrap();
+        let run = || {
+            let cars = (0..2).map(|car| ElevatorController::new(car, 0)).collect();
+            let building = Building::new(0, 9).unwrap();
+            let mut group = GroupDispatcher::new(
+                building,
+                cars,
+                Box::new(WeightedCost::default()),
+            );
+            replay(&entries, &mut group)
+        };
+        let commands = run();
+        assert_eq!(
+            commands,
+            vec![
+                (0, 0, Command::MoveUp),
+                (0, 1, Command::MoveUp),
+                (6000, 1, Command::Stop),
+                (6000, 1, Command::OpenDoor),
+            ]
+        );
+        assert_eq!(run(), commands);
+    }
+}
@@ -0,0 +1,328 @@
+use super::building::Building;
+use super::controller::{Command, ElevatorController, OperatingMode};
+use crate::{CarId, Direction, Event};
+
+/// Estimates how expensive it would be for a car to answer a lobby call.
+/// Lower is better.
+pub trait CostFunction {
+    fn cost(&self, car: &ElevatorController, floor: i32, dir: Direction) -> u32;
+}
+
+/// A cost made of weighted distance, direction and load terms. The sum
+/// saturates, so very large weights rank a car last rather than overflow.
+#[derive(Debug, Clone, Copy)]
+pub struct WeightedCost {
+    /// Cost per floor between the car and the call.
+    pub per_floor: u32,
+    /// Added when the car is travelling away from the call, or towards it
+    /// but in the opposite direction to the one the passenger wants.
+    pub wrong_direction: u32,
+    /// Cost per stop the car has already been assigned, as a measure of load.
+    pub per_stop: u32,
+}
+
+impl Default for WeightedCost {
+    fn default() -> WeightedCost {
+        WeightedCost { per_floor: 1, wrong_direction: 10, per_stop: 2 }
+    }
+}
+
+impl CostFunction for WeightedCost {
+    fn cost(&self, car: &ElevatorController, floor: i32, dir: Direction) -> u32 {
+        let distance = car.floor().abs_diff(floor);
+        let on_the_way = match car.moving() {
+            None => true,
+            Some(Direction::Up) => dir == Direction::Up && floor >= car.floor(),
+            Some(Direction::Down) => dir == Direction::Down && floor <= car.floor(),
+        };
+        let stops = u32::try_from(car.pending().len()).unwrap_or(u32::MAX);
+        let wrong_direction = if on_the_way { 0 } else { self.wrong_direction };
+        self.per_floor
+            .saturating_mul(distance)
+            .saturating_add(wrong_direction)
+            .saturating_add(self.per_stop.saturating_mul(stops))
+    }
+}
+
+/// Drives a bank of cars, assigning each lobby call to the cheapest car that
+/// stops on that floor.
+///
+/// A car that leaves normal service drops its pending stops, so the lobby calls
+/// it was assigned and has not yet answered are handed to the other cars.
+pub struct GroupDispatcher {
+    building: Building,
+    cars: Vec<ElevatorController>,
+    cost: Box<dyn CostFunction>,
+    /// Lobby calls waiting for the car they were assigned to.
+    lobby_calls: Vec<(CarId, i32, Direction)>,
+}
+
+impl GroupDispatcher {
+    /// Groups `cars` into a bank, setting each car's fire recall floor to the
+    /// building's.
+    pub fn new(
+        building: Building,
+        mut cars: Vec<ElevatorController>,
+        cost: Box<dyn CostFunction>,
+    ) -> GroupDispatcher {
+        for car in &mut cars {
+            car.set_recall_floor(building.recall_floor());
+        }
+        GroupDispatcher { building, cars, cost, lobby_calls: Vec::new() }
+    }
+
+    /// The building the bank serves.
+    pub fn building(&self) -> &Building {
+        &self.building
+    }
+
+    /// The controllers of every car in the bank.
+    pub fn cars(&self) -> &[ElevatorController] {
+        &self.cars
+    }
+
+    /// The car that should answer a lobby call, or `None` if no car in
+    /// normal service stops there. Ties go to the car listed first.
+    pub fn assign(&self, floor: i32, dir: Direction) -> Option<CarId> {
+        self.cars
+            .iter()
+            .filter(|car| car.mode() == OperatingMode::Normal)
+            .filter(|car| self.building.serves(car.car(), floor))
+            .min_by_key(|car| self.cost.cost(car, floor, dir))
+            .map(|car| car.car())
+    }
+
+    /// Routes `event` to the car it concerns and returns the resulting
+    /// commands, tagged with the car they are for. Buttons for floors the car
+    /// does not stop at are ignored.
+    pub fn handle(&mut self, event: Event) -> Vec<(CarId, Command)> {
+        let mut commands = self.route(event);
+        commands.extend(self.reassign());
+        commands
+    }
+
+    fn route(&mut self, event: Event) -> Vec<(CarId, Command)> {
+        let car = match event {
+            Event::FireRecallActivated | Event::FireRecallReset => {
+                let mut commands = Vec::new();
+                for controller in &mut self.cars {
+                    let car = controller.car();
+                    let sent = controller.handle(event.clone());
+                    commands.extend(sent.into_iter().map(|command| (car, command)));
+                }
+                return commands;
+            }
+            Event::LobbyCallButtonPressed(floor, dir) => self.assign(floor, dir),
+            Event::CarFloorButtonPressed(car, floor) => {
+                Some(car).filter(|&car| self.building.serves(car, floor))
+            }
+            Event::CarArrived(car, _)
+            | Event::CarDoorOpened(car)
+            | Event::CarDoorClosed(car)
+            | Event::CarModeSelected(car, _) => Some(car),
+        };
+        let Some(controller) = self.cars.iter_mut().find(|c| Some(c.car()) == car)
+        else {
+            return Vec::new();
+        };
+        let car = controller.car();
+        let lobby_call = match event {
+            Event::LobbyCallButtonPressed(floor, dir) => Some((floor, dir)),
+            _ => None,
+        };
+        let commands: Vec<_> = controller
+            .handle(event)
+            .into_iter()
+            .map(|command| (car, command))
+            .collect();
+        // A car already standing at the floor answers the call straight away.
+        if let Some((floor, dir)) = lobby_call {
+            if controller.pending().contains(&floor) {
+                self.lobby_calls.push((car, floor, dir));
+            }
+        }
+        commands
+    }
+
+    /// Forgets lobby calls that have been answered and assigns those of cars
+    /// no longer in normal service afresh. A call no car can take is dropped,
+    /// as it would have been when the button was pressed.
+    fn reassign(&mut self) -> Vec<(CarId, Command)> {
+        let mut stranded = Vec::new();
+        let cars = &self.cars;
+        self.lobby_calls.retain(|&(car, floor, dir)| {
+            let Some(controller) = cars.iter().find(|c| c.car() == car) else {
+                return false;
+            };
+            if controller.mode() != OperatingMode::Normal {
+                stranded.push((floor, dir));
+                return false;
+            }
+            controller.pending().contains(&floor)
+        });
+        stranded
+            .into_iter()
+            .flat_map(|(floor, dir)| {
+                self.route(Event::LobbyCallButtonPressed(floor, dir))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        car_arrived, car_floor_button_pressed, car_mode_selected,
+        fire_recall_activated, lobby_call_button_pressed, CarMode,
+    };
+
+    fn bank(floors: &[i32]) -> GroupDispatcher {
+        let cars = floors
+            .iter()
+            .enumerate()
+            .map(|(car, &floor)| ElevatorController::new(car, floor))
+            .collect();
+        let building = Building::new(0, 9).unwrap();
+        GroupDispatcher::new(building, cars, Box::new(WeightedCost::default()))
+    }
+
+    #[test]
+    fn nearest_idle_car_answers() {
+        let mut group = bank(&[0, 4, 9]);
+        assert_eq!(
+            group.handle(lobby_call_button_pressed(7, Direction::Down)),
+            vec![(2, Command::MoveDown)]
+        );
+        assert_eq!(
+            group.handle(lobby_call_button_pressed(4, Direction::Up)),
+            vec![(1, Command::OpenDoor)]
+        );
+    }
+
+    #[test]
+    fn prefers_car_already_heading_that_way() {
+        let mut group = bank(&[0, 6]);
+        group.handle(car_floor_button_pressed(0, 9));
+        group.handle(car_floor_button_pressed(1, 0));
+        group.handle(car_arrived(0, 3));
+        group.handle(car_arrived(1, 5));
+        // Car 1 is closer, but travelling away from an upward call at 6.
+        assert_eq!(group.assign(6, Direction::Up), Some(0));
+    }
+
+    #[test]
+    fn load_breaks_ties() {
+        let mut group = bank(&[4, 4]);
+        group.handle(car_floor_button_pressed(0, 8));
+        // Both cars are two floors away and neither would have to turn around.
+        assert_eq!(group.assign(6, Direction::Up), Some(1));
+    }
+
+    #[test]
+    fn custom_weights() {
+        let cars =
+            vec![ElevatorController::new(0, 0), ElevatorController::new(1, 9)];
+        let only_load =
+            WeightedCost { per_floor: 0, wrong_direction: 0, per_stop: 1 };
+        let building = Building::new(0, 9).unwrap();
+        let mut group = GroupDispatcher::new(building, cars, Box::new(only_load));
+        group.handle(car_floor_button_pressed(0, 5));
+        assert_eq!(group.assign(1, Direction::Up), Some(1));
+    }
+
+    #[test]
+    fn huge_weights_saturate() {
+        let cars = vec![
+            ElevatorController::new(0, -2_000_000_000),
+            ElevatorController::new(1, 9),
+        ];
+        let huge = WeightedCost {
+            per_floor: u32::MAX,
+            wrong_direction: u32::MAX,
+            per_stop: u32::MAX,
+        };
+        let building = Building::new(-2_000_000_000, 2_000_000_000).unwrap();
+        let mut group = GroupDispatcher::new(building, cars, Box::new(huge));
+        group.handle(car_floor_button_pressed(1, 2_000_000_000));
+        assert_eq!(
+            huge.cost(&group.cars()[0], 2_000_000_000, Direction::Up),
+            u32::MAX
+        );
+        assert_eq!(group.assign(10, Direction::Up), Some(0));
+    }
+
+    #[test]
+    fn unknown_car_is_ignored() {
+        let mut group = bank(&[0]);
+        assert_eq!(group.handle(car_arrived(7, 3)), vec![]);
+        assert_eq!(group.cars()[0].floor(), 0);
+    }
+
+    #[test]
+    fn express_cars_are_not_assigned_skipped_floors() {
+        let mut building = Building::new(0, 20).unwrap();
+        building.add_express_zone(1..=15, &[1]).unwrap();
+        let cars =
+            vec![ElevatorController::new(0, 0), ElevatorController::new(1, 10)];
+        let mut group =
+            GroupDispatcher::new(building, cars, Box::new(WeightedCost::default()));
+        assert_eq!(group.assign(10, Direction::Up), Some(0));
+        assert_eq!(group.assign(16, Direction::Up), Some(1));
+        assert_eq!(group.handle(car_floor_button_pressed(1, 5)), vec![]);
+        assert!(group.cars()[1].pending().is_empty());
+    }
+
+    #[test]
+    fn only_cars_in_normal_service_take_lobby_calls() {
+        let mut group = bank(&[5, 0]);
+        group.handle(car_mode_selected(0, CarMode::IndependentService));
+        assert_eq!(group.assign(5, Direction::Up), Some(1));
+        group.handle(car_mode_selected(1, CarMode::OutOfService));
+        assert_eq!(group.assign(5, Direction::Up), None);
+        assert_eq!(
+            group.handle(lobby_call_button_pressed(5, Direction::Up)),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn lobby_calls_move_to_another_car() {
+        let mut group = bank(&[0, 9]);
+        assert_eq!(
+            group.handle(lobby_call_button_pressed(2, Direction::Up)),
+            vec![(0, Command::MoveUp)]
+        );
+        group.handle(car_floor_button_pressed(0, 4));
+        assert_eq!(
+            group.handle(car_mode_selected(0, CarMode::OutOfService)),
+            vec![(1, Command::MoveDown)]
+        );
+        // The car call goes with the car; only the lobby call is reassigned.
+        assert_eq!(group.cars()[1].pending(), [2]);
+
+        // Once answered, a call is not reassigned again.
+        group.handle(car_arrived(1, 2));
+        assert_eq!(
+            group.handle(car_mode_selected(1, CarMode::IndependentService)),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn fire_recall_reaches_every_car() {
+        let mut building = Building::new(-1, 9).unwrap();
+        building.set_recall_floor(1).unwrap();
+        let cars =
+            (0..3).map(|car| ElevatorController::new(car, car as i32 * 3)).collect();
+        let mut group =
+            GroupDispatcher::new(building, cars, Box::new(WeightedCost::default()));
+        group.handle(car_mode_selected(2, CarMode::OutOfService));
+        assert_eq!(
+            group.handle(fire_recall_activated()),
+            vec![(0, Command::MoveUp), (1, Command::MoveDown)]
+        );
+        assert_eq!(group.cars()[0].mode(), OperatingMode::FireRecall);
+        assert_eq!(group.cars()[2].mode(), OperatingMode::OutOfService);
+    }
+}
@@ -0,0 +1,310 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use super::building::Building;
+use super::event_log::LogEntry;
+use crate::{CarId, Direction, Event};
+
+/// What a trace is checked against.
+#[derive(Debug, Clone)]
+pub struct SafetyRules {
+    /// The building the trace was recorded in.
+    pub building: Building,
+    /// The floor every car is on when the trace starts.
+    pub start_floor: i32,
+    /// How long, in milliseconds, a pressed button may go unanswered.
+    pub max_wait: u64,
+}
+
+/// A safety rule broken by a trace. Times are the timestamps of the entries
+/// at fault.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    /// The car reached a floor while its doors were not closed.
+    ArrivedWithDoorsOpen { time: u64, car: CarId, floor: i32 },
+    /// The doors reported opening while already open.
+    DoorsOpenedTwice { time: u64, car: CarId },
+    /// The doors reported closing while already closed.
+    DoorsClosedTwice { time: u64, car: CarId },
+    /// An event named a floor outside the building.
+    FloorOutOfRange { time: u64, event: Event },
+    /// A lobby call was not answered by any car in time.
+    LobbyCallUnanswered { time: u64, floor: i32, dir: Direction },
+    /// A car button was not answered by that car in time.
+    CarCallUnanswered { time: u64, car: CarId, floor: i32 },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Violation::ArrivedWithDoorsOpen { time, car, floor } => {
+                write!(
+                    f,
+                    "{time}: car {car} arrived at floor {floor} with its doors open"
+                )
+            }
+            Violation::DoorsOpenedTwice { time, car } => {
+                write!(f, "{time}: car {car} opened doors that were already open")
+            }
+            Violation::DoorsClosedTwice { time, car } => {
+                write!(f, "{time}: car {car} closed doors that were already closed")
+            }
+            Violation::FloorOutOfRange { time, event } => {
+                write!(f, "{time}: floor out of range in \"{event}\"")
+            }
+            Violation::LobbyCallUnanswered { time, floor, dir } => {
+                write!(f, "{time}: lobby call at floor {floor} going {dir} was not answered")
+            }
+            Violation::CarCallUnanswered { time, car, floor } => {
+                write!(
+                    f,
+                    "{time}: car {car} did not answer its button for floor {floor}"
+                )
+            }
+        }
+    }
+}
+
+/// What the checker knows about one car.
+struct CarState {
+    floor: i32,
+    doors_open: bool,
+}
+
+fn car_state<'a>(
+    cars: &'a mut HashMap<CarId, CarState>,
+    id: CarId,
+    rules: &SafetyRules,
+) -> &'a mut CarState {
+    cars.entry(id)
+        .or_insert(CarState { floor: rules.start_floor, doors_open: false })
+}
+
+/// Checks a recorded trace against `rules` and returns every violation found,
+/// in the order they occur.
+///
+/// A button counts as answered once a suitable car opens its doors on that
+/// floor, or right away if one is already standing there with its doors open.
+/// Buttons still unanswered when the trace ends are only reported if
+/// the trace runs past their deadline.
+pub fn check_trace(entries: &[LogEntry], rules: &SafetyRules) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    let mut cars: HashMap<CarId, CarState> = HashMap::new();
+    let mut lobby_calls: Vec<(u64, i32, Direction)> = Vec::new();
+    let mut car_calls: Vec<(u64, CarId, i32)> = Vec::new();
+    let in_range = |floor: i32| rules.building.contains(floor);
+
+    for entry in entries {
+        let time = entry.time;
+        // Report overdue buttons as soon as the trace passes their deadline.
+        let overdue = |pressed: u64| time > pressed.saturating_add(rules.max_wait);
+        violations.extend(lobby_calls.iter().filter(|c| overdue(c.0)).map(
+            |&(time, floor, dir)| Violation::LobbyCallUnanswered {
+                time,
+                floor,
+                dir,
+            },
+        ));
+        lobby_calls.retain(|c| !overdue(c.0));
+        violations.extend(car_calls.iter().filter(|c| overdue(c.0)).map(
+            |&(time, car, floor)| Violation::CarCallUnanswered { time, car, floor },
+        ));
+        car_calls.retain(|c| !overdue(c.0));
+
+        match entry.event {
+            Event::CarArrived(id, floor) => {
+                let car = car_state(&mut cars, id, rules);
+                if car.doors_open {
+                    violations.push(Violation::ArrivedWithDoorsOpen {
+                        time,
+                        car: id,
+                        floor,
+                    });
+                }
+                car.floor = floor;
+                if !in_range(floor) {
+                    violations.push(Violation::FloorOutOfRange {
+                        time,
+                        event: entry.event.clone(),
+                    });
+                }
+            }
+            Event::CarDoorOpened(id) => {
+                let car = car_state(&mut cars, id, rules);
+                if car.doors_open {
+                    violations.push(Violation::DoorsOpenedTwice { time, car: id });
+                }
+                car.doors_open = true;
+                let floor = car.floor;
+                lobby_calls.retain(|c| c.1 != floor);
+                car_calls.retain(|c| c.1 != id || c.2 != floor);
+            }
+            Event::CarDoorClosed(id) => {
+                let car = car_state(&mut cars, id, rules);
+                if !car.doors_open {
+                    violations.push(Violation::DoorsClosedTwice { time, car: id });
+                }
+                car.doors_open = false;
+            }
+            Event::LobbyCallButtonPressed(floor, dir) => {
+                if !in_range(floor) {
+                    violations.push(Violation::FloorOutOfRange {
+                        time,
+                        event: entry.event.clone(),
+                    });
+                } else if !cars
+                    .values()
+                    .any(|car| car.doors_open && car.floor == floor)
+                {
+                    lobby_calls.push((time, floor, dir));
+                }
+            }
+            Event::CarFloorButtonPressed(id, floor) => {
+                let car = car_state(&mut cars, id, rules);
+                if !in_range(floor) {
+                    violations.push(Violation::FloorOutOfRange {
+                        time,
+                        event: entry.event.clone(),
+                    });
+                } else if !(car.doors_open && car.floor == floor) {
+                    car_calls.push((time, id, floor));
+                }
+            }
+            Event::FireRecallActivated
+            | Event::FireRecallReset
+            | Event::CarModeSelected(..) => {}
+        }
+    }
+    violations
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        car_arrived, car_door_closed, car_door_opened, car_floor_button_pressed,
+        lobby_call_button_pressed,
+    };
+
+    fn rules() -> SafetyRules {
+        let building = Building::new(0, 5).unwrap();
+        SafetyRules { building, start_floor: 0, max_wait: 10_000 }
+    }
+
+    fn trace(events: Vec<(u64, Event)>) -> Vec<LogEntry> {
+        events.into_iter().map(|(time, event)| LogEntry { time, event }).collect()
+    }
+
+    #[test]
+    fn clean_trip_is_safe() {
+        let entries = trace(vec![
+            (0, lobby_call_button_pressed(0, Direction::Up)),
+            (1000, car_door_opened(0)),
+            (2000, car_floor_button_pressed(0, 2)),
+            (3000, car_door_closed(0)),
+            (5000, car_arrived(0, 1)),
+            (7000, car_arrived(0, 2)),
+            (9000, car_door_opened(0)),
+            (60_000, car_door_closed(0)),
+        ]);
+        assert_eq!(check_trace(&entries, &rules()), vec![]);
+    }
+
+    #[test]
+    fn door_violations() {
+        let entries = trace(vec![
+            (0, car_door_opened(0)),
+            (100, car_door_opened(0)),
+            (200, car_arrived(0, 1)),
+            (300, car_door_closed(0)),
+            (400, car_door_closed(0)),
+            (500, car_door_opened(1)),
+        ]);
+        assert_eq!(
+            check_trace(&entries, &rules()),
+            vec![
+                Violation::DoorsOpenedTwice { time: 100, car: 0 },
+                Violation::ArrivedWithDoorsOpen { time: 200, car: 0, floor: 1 },
+                Violation::DoorsClosedTwice { time: 400, car: 0 },
+            ]
+        );
+    }
+
+    #[test]
+    fn floors_out_of_range() {
+        let entries = trace(vec![
+            (0, car_arrived(0, 6)),
+            (1, lobby_call_button_pressed(-1, Direction::Up)),
+            (2, car_floor_button_pressed(0, 9)),
+        ]);
+        let violations = check_trace(&entries, &rules());
+        assert_eq!(violations.len(), 3);
+        assert!(violations
+            .iter()
+            .all(|v| matches!(v, Violation::FloorOutOfRange { .. })));
+    }
+
+    #[test]
+    fn unanswered_buttons() {
+        let entries = trace(vec![
+            (0, lobby_call_button_pressed(3, Direction::Down)),
+            (0, car_floor_button_pressed(0, 4)),
+            (0, car_floor_button_pressed(1, 2)),
+            // Car 1 answers its own button, but car 0's is still waiting.
+            (2000, car_arrived(1, 2)),
+            (2500, car_door_opened(1)),
+            (2500, car_arrived(0, 4)),
+            (20_000, car_arrived(0, 3)),
+        ]);
+        assert_eq!(
+            check_trace(&entries, &rules()),
+            vec![
+                Violation::LobbyCallUnanswered {
+                    time: 0,
+                    floor: 3,
+                    dir: Direction::Down
+                },
+                Violation::CarCallUnanswered { time: 0, car: 0, floor: 4 },
+            ]
+        );
+    }
+
+    #[test]
+    fn buttons_at_an_open_car_are_answered() {
+        let entries = trace(vec![
+            (0, car_arrived(1, 3)),
+            (500, car_door_opened(1)),
+            (1000, lobby_call_button_pressed(3, Direction::Up)),
+            (1000, car_floor_button_pressed(1, 3)),
+            (30_000, car_door_closed(1)),
+        ]);
+        assert_eq!(check_trace(&entries, &rules()), vec![]);
+    }
+
+    #[test]
+    fn unlimited_wait_never_expires() {
+        let rules = SafetyRules { max_wait: u64::MAX, ..rules() };
+        let entries = trace(vec![
+            (5, lobby_call_button_pressed(3, Direction::Down)),
+            (u64::MAX, lobby_call_button_pressed(2, Direction::Up)),
+        ]);
+        assert_eq!(check_trace(&entries, &rules), vec![]);
+    }
+
+    #[test]
+    fn pending_at_end_of_short_trace_is_not_reported() {
+        let entries =
+            trace(vec![(0, lobby_call_button_pressed(3, Direction::Down))]);
+        assert_eq!(check_trace(&entries, &rules()), vec![]);
+    }
+
+    #[test]
+    fn messages() {
+        let violation =
+            Violation::ArrivedWithDoorsOpen { time: 5, car: 2, floor: 3 };
+        assert_eq!(
+            violation.to_string(),
+            "5: car 2 arrived at floor 3 with its doors open"
+        );
+    }
+}
@@ -0,0 +1,183 @@
+use crate::Direction;
+
+/// Decides which pending floor the car should serve next.
+pub trait Scheduler {
+    /// A short name for reports and command-line selection.
+    fn name(&self) -> &'static str;
+
+    /// Picks the next floor to travel to, given the pending requests in the
+    /// order they were made, the car's floor and the direction it last
+    /// travelled in. Returning `floor` itself means "serve this floor now".
+    fn next(
+        &mut self,
+        pending: &[i32],
+        floor: i32,
+        heading: Direction,
+    ) -> Option<i32>;
+}
+
+/// First come, first served: requests are answered strictly in order.
+pub struct Fcfs;
+
+impl Scheduler for Fcfs {
+    fn name(&self) -> &'static str {
+        "fcfs"
+    }
+
+    fn next(
+        &mut self,
+        pending: &[i32],
+        _floor: i32,
+        _heading: Direction,
+    ) -> Option<i32> {
+        pending.first().copied()
+    }
+}
+
+/// Shortest seek first: always go to the closest pending floor.
+pub struct ShortestSeek;
+
+impl Scheduler for ShortestSeek {
+    fn name(&self) -> &'static str {
+        "sstf"
+    }
+
+    fn next(
+        &mut self,
+        pending: &[i32],
+        floor: i32,
+        _heading: Direction,
+    ) -> Option<i32> {
+        // `min_by_key` keeps the first minimum, so ties go to the older request.
+        pending.iter().copied().min_by_key(|f| (f - floor).abs())
+    }
+}
+
+/// Keep going in the current direction while there are requests ahead, then
+/// turn around.
+pub struct Look;
+
+impl Scheduler for Look {
+    fn name(&self) -> &'static str {
+        "look"
+    }
+
+    fn next(
+        &mut self,
+        pending: &[i32],
+        floor: i32,
+        heading: Direction,
+    ) -> Option<i32> {
+        nearest_ahead(pending, floor, heading)
+            .or_else(|| nearest_ahead(pending, floor, heading.reverse()))
+    }
+}
+
+/// Like `Look`, but always travel to the end of the shaft before turning
+/// around.
+pub struct Scan {
+    bottom: i32,
+    top: i32,
+}
+
+impl Scan {
+    /// Creates a scheduler for a shaft serving `bottom..=top`.
+    pub fn new(bottom: i32, top: i32) -> Scan {
+        Scan { bottom, top }
+    }
+}
+
+impl Scheduler for Scan {
+    fn name(&self) -> &'static str {
+        "scan"
+    }
+
+    fn next(
+        &mut self,
+        pending: &[i32],
+        floor: i32,
+        heading: Direction,
+    ) -> Option<i32> {
+        if let Some(target) = nearest_ahead(pending, floor, heading) {
+            return Some(target);
+        }
+        if pending.is_empty() {
+            return None;
+        }
+        let end = match heading {
+            Direction::Up => self.top,
+            Direction::Down => self.bottom,
+        };
+        if floor != end {
+            return Some(end);
+        }
+        nearest_ahead(pending, floor, heading.reverse())
+    }
+}
+
+/// The closest pending floor at or beyond `floor` in direction `heading`.
+fn nearest_ahead(pending: &[i32], floor: i32, heading: Direction) -> Option<i32> {
+    let ahead = pending.iter().copied();
+    match heading {
+        Direction::Up => ahead.filter(|&f| f >= floor).min(),
+        Direction::Down => ahead.filter(|&f| f <= floor).max(),
+    }
+}
+
+/// Builds a scheduler from its `name()`, for a shaft serving `bottom..=top`.
+pub fn scheduler_by_name(
+    name: &str,
+    bottom: i32,
+    top: i32,
+) -> Option<Box<dyn Scheduler>> {
+    match name {
+        "fcfs" => Some(Box::new(Fcfs)),
+        "sstf" => Some(Box::new(ShortestSeek)),
+        "look" => Some(Box::new(Look)),
+        "scan" => Some(Box::new(Scan::new(bottom, top))),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fcfs_keeps_request_order() {
+        assert_eq!(Fcfs.next(&[7, 2, 3], 2, Direction::Up), Some(7));
+        assert_eq!(Fcfs.next(&[], 2, Direction::Up), None);
+    }
+
+    #[test]
+    fn sstf_picks_closest() {
+        assert_eq!(ShortestSeek.next(&[9, 2, 6], 5, Direction::Down), Some(6));
+        // Ties go to the older request.
+        assert_eq!(ShortestSeek.next(&[7, 3], 5, Direction::Up), Some(7));
+    }
+
+    #[test]
+    fn look_turns_at_last_request() {
+        assert_eq!(Look.next(&[1, 8, 6], 4, Direction::Up), Some(6));
+        assert_eq!(Look.next(&[1, 3], 4, Direction::Up), Some(3));
+        assert_eq!(Look.next(&[1, 8, 6], 4, Direction::Down), Some(1));
+        assert_eq!(Look.next(&[4], 4, Direction::Down), Some(4));
+    }
+
+    #[test]
+    fn scan_runs_to_end_of_shaft() {
+        let mut scan = Scan::new(0, 9);
+        assert_eq!(scan.next(&[1, 6], 4, Direction::Up), Some(6));
+        assert_eq!(scan.next(&[1, 3], 4, Direction::Up), Some(9));
+        assert_eq!(scan.next(&[1, 3], 9, Direction::Up), Some(3));
+        assert_eq!(scan.next(&[], 4, Direction::Up), None);
+    }
+
+    #[test]
+    fn lookup_by_name() {
+        for name in ["fcfs", "sstf", "look", "scan"] {
+            assert_eq!(scheduler_by_name(name, 0, 9).unwrap().name(), name);
+        }
+        assert!(scheduler_by_name("elevator-music", 0, 9).is_none());
+    }
+}
@@ -0,0 +1,541 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fmt;
+
+use super::building::Building;
+use super::controller::{Command, ElevatorController};
+use super::group::{GroupDispatcher, WeightedCost};
+use super::scheduler::scheduler_by_name;
+use crate::{
+    car_arrived, car_door_closed, car_door_opened, car_floor_button_pressed,
+    lobby_call_button_pressed, CarId, Direction, Event,
+};
+
+/// A small, seedable pseudo-random number generator (SplitMix64), so that
+/// simulations are reproducible without pulling in a dependency.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A uniformly distributed value in `[0, 1)`.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// A uniformly distributed value in `low..=high`.
+    pub fn range(&mut self, low: i32, high: i32) -> i32 {
+        let span = high.abs_diff(low) as u64 + 1;
+        low + (self.next_u64() % span) as i32
+    }
+
+    /// An exponentially distributed value with the given mean.
+    pub fn exponential(&mut self, mean: f64) -> f64 {
+        -mean * (1.0 - self.next_f64()).ln()
+    }
+}
+
+/// The shape of the passenger traffic to generate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Traffic {
+    /// Most passengers travel up from the lobby, as in the morning.
+    UpPeak,
+    /// Most passengers travel down to the lobby, as in the evening.
+    DownPeak,
+    /// Passengers travel between random floors.
+    InterFloor,
+}
+
+impl Traffic {
+    pub fn from_name(name: &str) -> Option<Traffic> {
+        match name {
+            "up-peak" => Some(Traffic::UpPeak),
+            "down-peak" => Some(Traffic::DownPeak),
+            "inter-floor" => Some(Traffic::InterFloor),
+            _ => None,
+        }
+    }
+}
+
+/// Parameters of a simulation run. Times are in milliseconds.
+#[derive(Debug, Clone)]
+pub struct SimConfig {
+    pub bottom: i32,
+    pub top: i32,
+    pub cars: usize,
+    pub scheduler: String,
+    pub traffic: Traffic,
+    pub seed: u64,
+    /// How long passengers keep arriving for.
+    pub duration: u64,
+    /// Mean time between two passenger arrivals.
+    pub mean_interarrival: u64,
+    /// Time to travel one floor.
+    pub floor_time: u64,
+    /// Time for the doors to open or to close.
+    pub door_time: u64,
+    /// Time the doors stay open once asked to close, for boarding.
+    pub dwell_time: u64,
+}
+
+impl Default for SimConfig {
+    fn default() -> SimConfig {
+        SimConfig {
+            bottom: 0,
+            top: 9,
+            cars: 4,
+            scheduler: String::from("look"),
+            traffic: Traffic::InterFloor,
+            seed: 1,
+            duration: 3_600_000,
+            mean_interarrival: 15_000,
+            floor_time: 2_000,
+            door_time: 2_000,
+            dwell_time: 3_000,
+        }
+    }
+}
+
+/// Summary of a simulation run. Times are in milliseconds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    pub passengers: usize,
+    pub delivered: usize,
+    pub avg_wait: f64,
+    pub p95_wait: u64,
+    pub avg_journey: f64,
+    pub p95_journey: u64,
+    /// The share of simulated time each car spent moving.
+    pub utilisation: Vec<f64>,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "passengers:  {} ({} delivered)",
+            self.passengers, self.delivered
+        )?;
+        writeln!(
+            f,
+            "wait:        avg {:.1}s, p95 {:.1}s",
+            self.avg_wait / 1000.0,
+            self.p95_wait as f64 / 1000.0
+        )?;
+        writeln!(
+            f,
+            "journey:     avg {:.1}s, p95 {:.1}s",
+            self.avg_journey / 1000.0,
+            self.p95_journey as f64 / 1000.0
+        )?;
+        write!(f, "utilisation:")?;
+        for (car, share) in self.utilisation.iter().enumerate() {
+            write!(f, " car {car} {:.0}%", share * 100.0)?;
+        }
+        Ok(())
+    }
+}
+
+struct Passenger {
+    origin: i32,
+    destination: i32,
+    arrived: u64,
+    boarded: Option<u64>,
+    delivered: Option<u64>,
+}
+
+impl Passenger {
+    fn direction(&self) -> Direction {
+        if self.destination > self.origin {
+            Direction::Up
+        } else {
+            Direction::Down
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum SimEvent {
+    PassengerArrives(usize),
+    CarArrives(CarId, i32),
+    DoorOpened(CarId),
+    DoorClosed(CarId),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Doors {
+    Closed,
+    Opening,
+    Open,
+    Closing,
+}
+
+/// The physical state of a car, as opposed to what its controller believes.
+struct Car {
+    floor: i32,
+    moving: Option<Direction>,
+    moving_since: u64,
+    moving_total: u64,
+    doors: Doors,
+    riders: Vec<usize>,
+}
+
+/// Generates the passengers for a run, in order of arrival.
+fn passengers(config: &SimConfig, rng: &mut Rng) -> Vec<Passenger> {
+    let lobby = config.bottom;
+    let mut result = Vec::new();
+    let mut now = 0.0;
+    loop {
+        now += rng.exponential(config.mean_interarrival as f64);
+        if now >= config.duration as f64 {
+            return result;
+        }
+        // Even at peak times, one passenger in ten travels between floors.
+        let peak = rng.next_f64() < 0.9;
+        let (origin, destination) = match config.traffic {
+            Traffic::UpPeak if peak => (lobby, rng.range(lobby + 1, config.top)),
+            Traffic::DownPeak if peak => (rng.range(lobby + 1, config.top), lobby),
+            _ => {
+                let origin = rng.range(config.bottom, config.top);
+                let mut destination = rng.range(config.bottom, config.top - 1);
+                if destination >= origin {
+                    destination += 1;
+                }
+                (origin, destination)
+            }
+        };
+        result.push(Passenger {
+            origin,
+            destination,
+            arrived: now as u64,
+            boarded: None,
+            delivered: None,
+        });
+    }
+}
+
+struct Simulation<'a> {
+    config: &'a SimConfig,
+    now: u64,
+    seq: u64,
+    queue: BinaryHeap<Reverse<(u64, u64, SimEvent)>>,
+    group: GroupDispatcher,
+    cars: Vec<Car>,
+    passengers: Vec<Passenger>,
+    waiting: Vec<usize>,
+}
+
+impl Simulation<'_> {
+    fn schedule(&mut self, delay: u64, event: SimEvent) {
+        self.seq += 1;
+        self.queue.push(Reverse((self.now + delay, self.seq, event)));
+    }
+
+    fn send(&mut self, event: Event) {
+        for (car, command) in self.group.handle(event) {
+            self.apply(car, command);
+        }
+    }
+
+    /// Plays the part of the car hardware.
+    fn apply(&mut self, id: CarId, command: Command) {
+        let car = &mut self.cars[id];
+        let step = match command {
+            Command::MoveUp => 1,
+            Command::MoveDown => -1,
+            Command::Stop => {
+                if car.moving.take().is_some() {
+                    car.moving_total += self.now - car.moving_since;
+                }
+                return;
+            }
+            Command::OpenDoor => {
+                if car.doors == Doors::Closed {
+                    car.doors = Doors::Opening;
+                    self.schedule(self.config.door_time, SimEvent::DoorOpened(id));
+                }
+                return;
+            }
+            Command::CloseDoor => {
+                if car.doors == Doors::Open {
+                    car.doors = Doors::Closing;
+                    let delay = self.config.dwell_time + self.config.door_time;
+                    self.schedule(delay, SimEvent::DoorClosed(id));
+                }
+                return;
+            }
+        };
+        if car.moving.is_none() {
+            car.moving_since = self.now;
+        }
+        car.moving = Some(if step > 0 { Direction::Up } else { Direction::Down });
+        let next = car.floor + step;
+        self.schedule(self.config.floor_time, SimEvent::CarArrives(id, next));
+    }
+
+    /// Lets everyone waiting on the car's floor board it.
+    fn board(&mut self, id: CarId) {
+        let floor = self.cars[id].floor;
+        let (boarding, waiting) =
+            self.waiting.iter().partition(|&&p| self.passengers[p].origin == floor);
+        self.waiting = waiting;
+        for p in boarding {
+            self.passengers[p].boarded = Some(self.now);
+            self.cars[id].riders.push(p);
+            self.send(car_floor_button_pressed(id, self.passengers[p].destination));
+        }
+    }
+
+    fn step(&mut self, event: SimEvent) {
+        match event {
+            SimEvent::PassengerArrives(p) => {
+                let floor = self.passengers[p].origin;
+                let dir = self.passengers[p].direction();
+                let lit = self.waiting.iter().any(|&q| {
+                    self.passengers[q].origin == floor
+                        && self.passengers[q].direction() == dir
+                });
+                self.waiting.push(p);
+                let open_car = self.cars.iter().position(|car| {
+                    car.floor == floor
+                        && car.moving.is_none()
+                        && matches!(car.doors, Doors::Open | Doors::Closing)
+                });
+                match open_car {
+                    Some(id) => self.board(id),
+                    None if !lit => self.send(lobby_call_button_pressed(floor, dir)),
+                    None => {}
+                }
+            }
+            SimEvent::CarArrives(id, floor) => {
+                self.cars[id].floor = floor;
+                let commands = self.group.handle(car_arrived(id, floor));
+                if commands.is_empty() {
+                    // The controller wants the car to carry on past this floor.
+                    let command = match self.cars[id].moving {
+                        Some(Direction::Up) => Command::MoveUp,
+                        Some(Direction::Down) => Command::MoveDown,
+                        None => return,
+                    };
+                    self.apply(id, command);
+                }
+                for (car, command) in commands {
+                    self.apply(car, command);
+                }
+            }
+            SimEvent::DoorOpened(id) => {
+                self.cars[id].doors = Doors::Open;
+                let floor = self.cars[id].floor;
+                let now = self.now;
+                let passengers = &mut self.passengers;
+                self.cars[id].riders.retain(|&p| {
+                    if passengers[p].destination != floor {
+                        return true;
+                    }
+                    passengers[p].delivered = Some(now);
+                    false
+                });
+                self.send(car_door_opened(id));
+                self.board(id);
+            }
+            SimEvent::DoorClosed(id) => {
+                self.cars[id].doors = Doors::Closed;
+                self.send(car_door_closed(id));
+            }
+        }
+    }
+}
+
+/// Runs a simulation and summarises how well the passengers were served.
+pub fn simulate(config: &SimConfig) -> Result<Report, String> {
+    let building =
+        Building::new(config.bottom, config.top).map_err(|e| e.to_string())?;
+    // Every passenger travels to a different floor.
+    if config.bottom == config.top {
+        return Err(format!(
+            "cannot simulate a building with only floor {}",
+            config.bottom
+        ));
+    }
+    let mut rng = Rng::new(config.seed);
+    let mut controllers = Vec::new();
+    for car in 0..config.cars {
+        let Some(scheduler) =
+            scheduler_by_name(&config.scheduler, config.bottom, config.top)
+        else {
+            return Err(format!("unknown scheduler {:?}", config.scheduler));
+        };
+        controllers.push(ElevatorController::with_scheduler(
+            car,
+            config.bottom,
+            scheduler,
+        ));
+    }
+    let mut sim = Simulation {
+        config,
+        now: 0,
+        seq: 0,
+        queue: BinaryHeap::new(),
+        group: GroupDispatcher::new(
+            building,
+            controllers,
+            Box::new(WeightedCost::default()),
+        ),
+        cars: (0..config.cars)
+            .map(|_| Car {
+                floor: config.bottom,
+                moving: None,
+                moving_since: 0,
+                moving_total: 0,
+                doors: Doors::Closed,
+                riders: Vec::new(),
+            })
+            .collect(),
+        passengers: passengers(config, &mut rng),
+        waiting: Vec::new(),
+    };
+    for p in 0..sim.passengers.len() {
+        let arrived = sim.passengers[p].arrived;
+        sim.queue.push(Reverse((arrived, p as u64, SimEvent::PassengerArrives(p))));
+    }
+    sim.seq = sim.passengers.len() as u64;
+    // Give the cars time to deliver the last passengers, but don't loop forever
+    // if a scheduler strands someone.
+    let deadline = config.duration * 10;
+    while let Some(Reverse((time, _, event))) = sim.queue.pop() {
+        if time > deadline {
+            break;
+        }
+        sim.now = time;
+        sim.step(event);
+    }
+
+    let end = sim.now.max(config.duration).max(1);
+    let mut waits: Vec<u64> =
+        sim.passengers.iter().filter_map(|p| Some(p.boarded? - p.arrived)).collect();
+    let mut journeys: Vec<u64> = sim
+        .passengers
+        .iter()
+        .filter_map(|p| Some(p.delivered? - p.arrived))
+        .collect();
+    let utilisation = sim
+        .cars
+        .iter()
+        .map(|car| {
+            let mut total = car.moving_total;
+            if car.moving.is_some() {
+                total += end - car.moving_since;
+            }
+            total as f64 / end as f64
+        })
+        .collect();
+    Ok(Report {
+        passengers: sim.passengers.len(),
+        delivered: journeys.len(),
+        avg_wait: mean(&waits),
+        p95_wait: percentile(&mut waits, 95),
+        avg_journey: mean(&journeys),
+        p95_journey: percentile(&mut journeys, 95),
+        utilisation,
+    })
+}
+
+fn mean(values: &[u64]) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.iter().sum::<u64>() as f64 / values.len() as f64
+}
+
+/// The nearest-rank percentile of `values`, or 0 if there are none.
+fn percentile(values: &mut [u64], pct: usize) -> u64 {
+    if values.is_empty() {
+        return 0;
+    }
+    values.sort_unstable();
+    let rank = (values.len() * pct).div_ceil(100);
+    values[rank.max(1) - 1]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn short_run(traffic: Traffic, seed: u64) -> SimConfig {
+        SimConfig { traffic, seed, duration: 600_000, ..SimConfig::default() }
+    }
+
+    #[test]
+    fn same_seed_same_report() {
+        let config = short_run(Traffic::UpPeak, 7);
+        assert_eq!(simulate(&config), simulate(&config));
+        let other = short_run(Traffic::UpPeak, 8);
+        assert_ne!(simulate(&config), simulate(&other));
+    }
+
+    #[test]
+    fn everyone_is_delivered() {
+        for traffic in [Traffic::UpPeak, Traffic::DownPeak, Traffic::InterFloor] {
+            for scheduler in ["fcfs", "sstf", "look", "scan"] {
+                let config = SimConfig {
+                    scheduler: String::from(scheduler),
+                    ..short_run(traffic, 3)
+                };
+                let report = simulate(&config).unwrap();
+                assert!(report.passengers > 0);
+                assert_eq!(report.delivered, report.passengers, "{scheduler}");
+                assert!(report.avg_wait <= report.avg_journey);
+                assert!(report
+                    .utilisation
+                    .iter()
+                    .all(|&u| (0.0..=1.0).contains(&u)));
+            }
+        }
+    }
+
+    #[test]
+    fn up_peak_starts_in_lobby() {
+        let config = short_run(Traffic::UpPeak, 11);
+        let all = passengers(&config, &mut Rng::new(config.seed));
+        let from_lobby = all.iter().filter(|p| p.origin == config.bottom).count();
+        assert!(from_lobby * 10 >= all.len() * 8);
+        assert!(all.iter().all(|p| p.origin != p.destination));
+    }
+
+    #[test]
+    fn unknown_scheduler() {
+        let config = SimConfig {
+            scheduler: String::from("coin-toss"),
+            ..SimConfig::default()
+        };
+        assert!(simulate(&config).is_err());
+    }
+
+    #[test]
+    fn single_floor() {
+        let config = SimConfig { bottom: 3, top: 3, ..SimConfig::default() };
+        assert_eq!(
+            simulate(&config).unwrap_err(),
+            "cannot simulate a building with only floor 3"
+        );
+    }
+
+    #[test]
+    fn percentiles() {
+        assert_eq!(percentile(&mut [], 95), 0);
+        assert_eq!(percentile(&mut [5], 95), 5);
+        let mut values: Vec<u64> = (1..=100).rev().collect();
+        assert_eq!(percentile(&mut values, 95), 95);
+        assert_eq!(percentile(&mut values, 100), 100);
+    }
+}
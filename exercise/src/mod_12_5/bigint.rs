@@ -0,0 +1,339 @@
+//! 任意精度の整数。
+
+use std::cmp::Ordering;
+use std::fmt;
+
+use super::number::{power, Number};
+use crate::EvalError;
+
+/// 1 桁の基数。10 進で表示しやすいように 10^9 を使う。
+const BASE: u64 = 1_000_000_000;
+
+/// 累乗の結果として許す桁数 (基数 10^9 で)。これを超えそうなら `Overflow`。
+const MAX_POW_DIGITS: u64 = 10_000;
+
+/// 符号と、基数 10^9 の絶対値 (下位の桁から順)。0 は空の桁列で、負にならない。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BigInt {
+    negative: bool,
+    digits: Vec<u32>,
+}
+
+impl BigInt {
+    fn new(negative: bool, mut digits: Vec<u32>) -> BigInt {
+        while digits.last() == Some(&0) {
+            digits.pop();
+        }
+        BigInt { negative: negative && !digits.is_empty(), digits }
+    }
+
+    fn negate(mut self) -> BigInt {
+        self.negative = !self.negative && !self.digits.is_empty();
+        self
+    }
+
+    /// 絶対値が `u64` に収まればその値。
+    fn magnitude(&self) -> Option<u64> {
+        self.digits
+            .iter()
+            .rev()
+            .try_fold(0u64, |acc, &d| acc.checked_mul(BASE)?.checked_add(d.into()))
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &BigInt) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => compare(&self.digits, &other.digits),
+            (true, true) => compare(&other.digits, &self.digits),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &BigInt) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl From<i64> for BigInt {
+    fn from(v: i64) -> BigInt {
+        let mut magnitude = v.unsigned_abs();
+        let mut digits = Vec::new();
+        while magnitude > 0 {
+            digits.push((magnitude % BASE) as u32);
+            magnitude /= BASE;
+        }
+        BigInt::new(v < 0, digits)
+    }
+}
+
+fn compare(a: &[u32], b: &[u32]) -> Ordering {
+    a.len().cmp(&b.len()).then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut sum = Vec::with_capacity(a.len().max(b.len()) + 1);
+    let mut carry = 0;
+    for i in 0..a.len().max(b.len()) {
+        let d = u64::from(*a.get(i).unwrap_or(&0))
+            + u64::from(*b.get(i).unwrap_or(&0))
+            + carry;
+        sum.push((d % BASE) as u32);
+        carry = d / BASE;
+    }
+    sum.push(carry as u32);
+    sum
+}
+
+/// `a - b`。`a >= b` であること。
+fn sub(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut difference = Vec::with_capacity(a.len());
+    let mut borrow = 0;
+    for (i, &d) in a.iter().enumerate() {
+        let mut d = i64::from(d) - i64::from(*b.get(i).unwrap_or(&0)) - borrow;
+        borrow = i64::from(d < 0);
+        if d < 0 {
+            d += BASE as i64;
+        }
+        difference.push(d as u32);
+    }
+    difference
+}
+
+fn mul(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut product = vec![0u64; a.len() + b.len() + 1];
+    for (i, &x) in a.iter().enumerate() {
+        let mut carry = 0;
+        for (j, &y) in b.iter().enumerate() {
+            let d = product[i + j] + u64::from(x) * u64::from(y) + carry;
+            product[i + j] = d % BASE;
+            carry = d / BASE;
+        }
+        product[i + b.len()] += carry;
+    }
+    product.into_iter().map(|d| d as u32).collect()
+}
+
+/// `a / b` の商。余りは捨てる。`b` は 0 でないこと。
+fn div(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut quotient = vec![0; a.len()];
+    let mut remainder: Vec<u32> = Vec::new();
+    for (i, &d) in a.iter().enumerate().rev() {
+        remainder.insert(0, d);
+        remainder = BigInt::new(false, remainder).digits;
+        // この桁の商を二分探索する。
+        let (mut lo, mut hi) = (0, BASE - 1);
+        while lo < hi {
+            let mid = (lo + hi).div_ceil(2);
+            if compare(&BigInt::new(false, mul(b, &[mid as u32])).digits, &remainder)
+                == Ordering::Greater
+            {
+                hi = mid - 1;
+            } else {
+                lo = mid;
+            }
+        }
+        quotient[i] = lo as u32;
+        remainder = sub(&remainder, &mul(b, &[lo as u32]));
+    }
+    quotient
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let Some((most, rest)) = self.digits.split_last() else {
+            return write!(f, "0");
+        };
+        if self.negative {
+            write!(f, "-")?;
+        }
+        write!(f, "{most}")?;
+        for d in rest.iter().rev() {
+            write!(f, "{d:09}")?;
+        }
+        Ok(())
+    }
+}
+
+/// 桁あふれはせず、除算はゼロ方向に切り捨てる。ゼロでの除算は
+/// `DivisionByZero`。ただし累乗は指数が 0 以上に限り、結果が大きすぎれば
+/// `Overflow` にする。
+impl Number for BigInt {
+    fn zero() -> BigInt {
+        BigInt::new(false, Vec::new())
+    }
+
+    fn one() -> BigInt {
+        BigInt::from(1)
+    }
+
+    fn from_literal(text: &str) -> Option<BigInt> {
+        let (negative, magnitude) = match text.strip_prefix('-') {
+            Some(magnitude) => (true, magnitude),
+            None => (false, text),
+        };
+        if magnitude.is_empty() || !magnitude.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let digits = magnitude
+            .as_bytes()
+            .rchunks(9)
+            .map(|chunk| std::str::from_utf8(chunk).ok()?.parse().ok())
+            .collect::<Option<Vec<u32>>>()?;
+        Some(BigInt::new(negative, digits))
+    }
+
+    fn try_add(&self, rhs: &BigInt) -> Result<BigInt, EvalError> {
+        if self.negative == rhs.negative {
+            return Ok(BigInt::new(self.negative, add(&self.digits, &rhs.digits)));
+        }
+        // 符号が違えば、絶対値の大きい方から小さい方を引く。
+        Ok(match compare(&self.digits, &rhs.digits) {
+            Ordering::Less => {
+                BigInt::new(rhs.negative, sub(&rhs.digits, &self.digits))
+            }
+            _ => BigInt::new(self.negative, sub(&self.digits, &rhs.digits)),
+        })
+    }
+
+    fn try_sub(&self, rhs: &BigInt) -> Result<BigInt, EvalError> {
+        self.try_add(&rhs.clone().negate())
+    }
+
+    fn try_mul(&self, rhs: &BigInt) -> Result<BigInt, EvalError> {
+        Ok(BigInt::new(
+            self.negative != rhs.negative,
+            mul(&self.digits, &rhs.digits),
+        ))
+    }
+
+    fn try_div(&self, rhs: &BigInt) -> Result<BigInt, EvalError> {
+        if rhs.digits.is_empty() {
+            return Err(EvalError::DivisionByZero);
+        }
+        Ok(BigInt::new(
+            self.negative != rhs.negative,
+            div(&self.digits, &rhs.digits),
+        ))
+    }
+
+    fn try_rem(&self, rhs: &BigInt) -> Result<BigInt, EvalError> {
+        self.try_sub(&self.try_div(rhs)?.try_mul(rhs)?)
+    }
+
+    fn try_pow(&self, exp: &BigInt) -> Result<BigInt, EvalError> {
+        if exp.negative {
+            return Err(EvalError::InvalidExponent);
+        }
+        // 0、1、-1 の累乗は指数がどれだけ大きくても大きくならないので、指数が
+        // 0 か奇数か偶数かだけを見ればよい。
+        if self.digits.len() <= 1 && self.digits.first().is_none_or(|&d| d == 1) {
+            let small = match exp.digits.first() {
+                None => 0,
+                Some(d) => 2 - u64::from(d % 2),
+            };
+            return power(self, small);
+        }
+        match exp.magnitude() {
+            Some(e)
+                if (self.digits.len() as u64).saturating_mul(e)
+                    <= MAX_POW_DIGITS =>
+            {
+                power(self, e)
+            }
+            _ => Err(EvalError::Overflow),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn big(text: &str) -> BigInt {
+        BigInt::from_literal(text).unwrap()
+    }
+
+    #[test]
+    fn display_and_literals() {
+        assert_eq!(big("-000123").to_string(), "-123");
+        assert_eq!(big("-0").to_string(), "0");
+        assert_eq!(
+            big("1000000000000000000001").to_string(),
+            "1000000000000000000001"
+        );
+        assert_eq!(BigInt::from(i64::MIN).to_string(), "-9223372036854775808");
+        assert_eq!(BigInt::from_literal("2.5"), None);
+    }
+
+    #[test]
+    fn arithmetic() {
+        let max = BigInt::from(i64::MAX);
+        assert_eq!(max.try_add(&BigInt::one()), Ok(big("9223372036854775808")));
+        assert_eq!(big("5").try_sub(&big("12")), Ok(big("-7")));
+        assert_eq!(big("-5").try_sub(&big("-5")), Ok(BigInt::zero()));
+        assert_eq!(
+            max.try_mul(&max),
+            Ok(big("85070591730234615847396907784232501249"))
+        );
+        assert_eq!(big("-7").try_div(&big("2")), Ok(big("-3")));
+        assert_eq!(
+            big("85070591730234615847396907784232501250").try_div(&max),
+            Ok(max.clone())
+        );
+        assert_eq!(
+            big("999999999999").try_div(&big("1000000000000")),
+            Ok(BigInt::zero())
+        );
+        assert_eq!(
+            big("1").try_div(&BigInt::zero()),
+            Err(EvalError::DivisionByZero)
+        );
+        assert_eq!(big("-7").try_rem(&big("2")), Ok(big("-1")));
+        assert_eq!(
+            big("2").try_pow(&big("100")),
+            Ok(big("1267650600228229401496703205376"))
+        );
+        assert_eq!(big("-1").try_pow(&big("1000000000000000000001")), Ok(big("-1")));
+        assert_eq!(big("0").try_pow(&big("0")), Ok(BigInt::one()));
+        assert_eq!(big("2").try_pow(&big("-1")), Err(EvalError::InvalidExponent));
+        assert_eq!(big("10").try_pow(&big("1000000")), Err(EvalError::Overflow));
+        assert!(
+            big("-5") < big("3") && big("-5") < big("-4") && big("12") > big("9")
+        );
+    }
+
+    #[test]
+    fn agrees_with_i128() {
+        let samples = [
+            0i64,
+            1,
+            -1,
+            7,
+            -13,
+            999_999_999,
+            1_000_000_000,
+            -123_456_789_012,
+            i64::MAX,
+            i64::MIN,
+        ];
+        for &a in &samples {
+            for &b in &samples {
+                let (x, y) = (BigInt::from(a), BigInt::from(b));
+                let (a, b) = (i128::from(a), i128::from(b));
+                assert_eq!(x.try_add(&y).unwrap().to_string(), (a + b).to_string());
+                assert_eq!(x.try_sub(&y).unwrap().to_string(), (a - b).to_string());
+                assert_eq!(x.try_mul(&y).unwrap().to_string(), (a * b).to_string());
+                if b != 0 {
+                    assert_eq!(
+                        x.try_div(&y).unwrap().to_string(),
+                        (a / b).to_string()
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,315 @@
+//! `Expression` をスタックマシンのバイトコードにコンパイルし、何度でも実行する。
+//!
+//! 変数名はコンパイル時に解決する。`let` で束縛した名前はローカルスロットに、
+//! それ以外の名前は入力の番号になり、実行のたびに `Environment` から値を
+//! 取り出す。`if` は条件付きのジャンプになり、選ばれなかった枝は実行しない。
+
+use crate::mod_12_5::environment::Environment;
+use crate::mod_12_5::fold::{fold, Fold};
+use crate::mod_12_5::number::Number;
+use crate::mod_12_5::types::{apply, negate, Scalar};
+use crate::{EvalError, Expression, Operation};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Instruction<N = i64> {
+    /// 定数を積む。
+    Push(N),
+    /// 真偽値の定数を積む。
+    PushBool(bool),
+    /// 入力 (自由変数) の値を積む。
+    Input(usize),
+    /// ローカルスロットの値を積む。
+    Local(usize),
+    /// 値を降ろしてローカルスロットに入れる。
+    Store(usize),
+    /// 2 つの値を降ろして演算し、結果を積む。
+    Op(Operation),
+    Neg,
+    /// 指定の位置へ飛ぶ。
+    Jump(usize),
+    /// 真偽値を降ろし、偽なら指定の位置へ飛ぶ。
+    JumpUnless(usize),
+}
+
+/// コンパイル済みの式。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program<N = i64> {
+    code: Vec<Instruction<N>>,
+    /// `Instruction::Input` の番号が指す変数名。
+    inputs: Vec<String>,
+    /// 必要なローカルスロットの数。
+    locals: usize,
+    /// 実行中のスタックの最大の深さ。
+    max_stack: usize,
+}
+
+struct Compiler<N> {
+    program: Program<N>,
+    /// 今見えている `let` の名前。添字がスロット番号。
+    scope: Vec<String>,
+    /// 飛び先がまだ決まっていないジャンプの位置。内側の `if` のものほど後ろに
+    /// ある。
+    jumps: Vec<usize>,
+    depth: usize,
+}
+
+impl<N> Compiler<N> {
+    fn emit(&mut self, instruction: Instruction<N>) {
+        match instruction {
+            Instruction::Push(_)
+            | Instruction::PushBool(_)
+            | Instruction::Input(_)
+            | Instruction::Local(_) => {
+                self.depth += 1;
+                self.program.max_stack = self.program.max_stack.max(self.depth);
+            }
+            Instruction::Neg | Instruction::Jump(_) => {}
+            Instruction::Store(_)
+            | Instruction::Op(_)
+            | Instruction::JumpUnless(_) => self.depth -= 1,
+        }
+        self.program.code.push(instruction);
+    }
+
+    /// 飛び先を仮に置いたジャンプを出力し、その位置を返す。飛び先はあとで
+    /// `land` で決める。
+    fn jump(&mut self, instruction: fn(usize) -> Instruction<N>) -> usize {
+        self.emit(instruction(usize::MAX));
+        self.program.code.len() - 1
+    }
+
+    /// `at` のジャンプの飛び先を、次に出力する位置にする。
+    fn land(&mut self, at: usize) {
+        let here = self.program.code.len();
+        match &mut self.program.code[at] {
+            Instruction::Jump(target) | Instruction::JumpUnless(target) => {
+                *target = here
+            }
+            _ => unreachable!("only jumps are pending"),
+        }
+    }
+
+    fn input(&mut self, name: &str) -> usize {
+        let inputs = &mut self.program.inputs;
+        inputs.iter().position(|n| n == name).unwrap_or_else(|| {
+            inputs.push(name.to_owned());
+            inputs.len() - 1
+        })
+    }
+}
+
+impl<N: Clone> Fold<N> for Compiler<N> {
+    type Output = ();
+
+    fn value(&mut self, v: &N) {
+        self.emit(Instruction::Push(v.clone()));
+    }
+
+    fn boolean(&mut self, b: bool) {
+        self.emit(Instruction::PushBool(b));
+    }
+
+    fn var(&mut self, name: &str) {
+        let instruction = match self.scope.iter().rposition(|n| n == name) {
+            Some(slot) => Instruction::Local(slot),
+            None => Instruction::Input(self.input(name)),
+        };
+        self.emit(instruction);
+    }
+
+    fn op(&mut self, op: Operation, (): (), (): ()) {
+        self.emit(Instruction::Op(op));
+    }
+
+    fn neg(&mut self, (): ()) {
+        self.emit(Instruction::Neg);
+    }
+
+    fn bind(&mut self, name: &str, (): &()) {
+        self.emit(Instruction::Store(self.scope.len()));
+        self.scope.push(name.to_owned());
+        self.program.locals = self.program.locals.max(self.scope.len());
+    }
+
+    fn let_in(&mut self, _: &str, (): (), (): ()) {
+        self.scope.pop();
+    }
+
+    fn enter_then(&mut self, (): &()) {
+        let skip_then = self.jump(Instruction::JumpUnless);
+        self.jumps.push(skip_then);
+    }
+
+    fn enter_else(&mut self, (): &()) {
+        let skip_then = self.jumps.pop().expect("the condition is compiled first");
+        let skip_else = self.jump(Instruction::Jump);
+        self.jumps.push(skip_else);
+        self.land(skip_then);
+        // 実行されるのはどちらか一方の枝なので、`else` 節は `then` 節と同じ
+        // 深さから始まる。
+        self.depth -= 1;
+    }
+
+    fn if_else(&mut self, (): (), (): (), (): ()) {
+        let skip_else = self.jumps.pop().expect("the then branch is compiled first");
+        self.land(skip_else);
+    }
+}
+
+/// `e` をコンパイルする。`e` はそのまま残る。
+pub fn compile<N: Number>(e: &Expression<N>) -> Program<N> {
+    let program =
+        Program { code: Vec::new(), inputs: Vec::new(), locals: 0, max_stack: 0 };
+    let mut compiler =
+        Compiler { program, scope: Vec::new(), jumps: Vec::new(), depth: 0 };
+    fold(e, &mut compiler);
+    compiler.program
+}
+
+impl<N: Number> Program<N> {
+    pub fn code(&self) -> &[Instruction<N>] {
+        &self.code
+    }
+
+    /// 式が参照する自由変数の名前。
+    pub fn inputs(&self) -> &[String] {
+        &self.inputs
+    }
+
+    /// `env` の下で実行する。結果もエラーも `eval` と同じになる。
+    pub fn run(&self, env: &Environment<N>) -> Result<N, EvalError> {
+        self.run_scalar(env)?.number()
+    }
+
+    /// `run` と同じだが、真偽値の結果も返す。
+    pub fn run_scalar(&self, env: &Environment<N>) -> Result<Scalar<N>, EvalError> {
+        // 束縛されていない変数は、実際に読まれたときに初めてエラーにする。
+        let inputs: Vec<Option<N>> =
+            self.inputs.iter().map(|name| env.get(name)).collect();
+        let mut locals = vec![Scalar::Number(N::zero()); self.locals];
+        let mut stack = Vec::with_capacity(self.max_stack);
+        let mut pc = 0;
+        while let Some(instruction) = self.code.get(pc) {
+            pc += 1;
+            match *instruction {
+                Instruction::Push(ref v) => stack.push(Scalar::Number(v.clone())),
+                Instruction::PushBool(b) => stack.push(Scalar::Bool(b)),
+                Instruction::Input(i) => {
+                    let value = inputs[i].clone().ok_or_else(|| {
+                        EvalError::UnboundVariable(self.inputs[i].clone())
+                    })?;
+                    stack.push(Scalar::Number(value));
+                }
+                Instruction::Local(slot) => stack.push(locals[slot].clone()),
+                Instruction::Store(slot) => locals[slot] = pop(&mut stack),
+                Instruction::Op(op) => {
+                    let right = pop(&mut stack);
+                    let left = pop(&mut stack);
+                    stack.push(apply(op, &left, &right)?);
+                }
+                Instruction::Neg => {
+                    let operand = pop(&mut stack);
+                    stack.push(negate(&operand)?);
+                }
+                Instruction::Jump(target) => pc = target,
+                Instruction::JumpUnless(target) => {
+                    if !pop(&mut stack).bool()? {
+                        pc = target;
+                    }
+                }
+            }
+        }
+        Ok(pop(&mut stack))
+    }
+}
+
+fn pop<N>(stack: &mut Vec<N>) -> N {
+    stack.pop().expect("compiled code is balanced")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::eval;
+    use crate::mod_12_5::parser::parse;
+
+    #[test]
+    fn code() {
+        let program = compile(&parse("let y = x * 2 in y - x").unwrap());
+        assert_eq!(
+            program.code(),
+            [
+                Instruction::Input(0),
+                Instruction::Push(2),
+                Instruction::Op(Operation::Mul),
+                Instruction::Store(0),
+                Instruction::Local(0),
+                Instruction::Input(0),
+                Instruction::Op(Operation::Sub),
+            ]
+        );
+        assert_eq!(program.inputs(), ["x"]);
+    }
+
+    #[test]
+    fn jumps() {
+        let program = compile(&parse("if x < 0 then -x else x").unwrap());
+        assert_eq!(
+            program.code(),
+            [
+                Instruction::Input(0),
+                Instruction::Push(0),
+                Instruction::Op(Operation::Lt),
+                Instruction::JumpUnless(7),
+                Instruction::Input(0),
+                Instruction::Neg,
+                Instruction::Jump(8),
+                Instruction::Input(0),
+            ]
+        );
+        assert_eq!(program.max_stack, 2);
+        for x in [-3, 0, 5] {
+            assert_eq!(program.run(&Environment::from([("x", x)])), Ok(x.abs()));
+        }
+    }
+
+    #[test]
+    fn same_results_as_eval() {
+        let inputs = [
+            "(3 - 4) * 5 + 10 * 9",
+            "x * x - y / z",
+            "let a = x + 1 in let b = a * a in (let a = b - y in a * z) + a",
+            "let x = x * 2 in let x = x * 3 in x",
+            "x / (y - y)",
+            "y + missing",
+            "(1 / 0) + missing",
+            "x * 4611686018427387904",
+            "-9223372036854775808 / (z - 2)",
+            "if x > y then x % z else y ^ 2",
+            "let big = x >= 3 in if big then if y < 0 then -y else y else 0",
+            "if z == 0 then 1 / z else missing",
+            "if x then 1 else 2",
+            "(x < y) + 1",
+            "-(x != y)",
+            "(x == y) == (y == x)",
+        ];
+        for (x, y, z) in [(0, 0, 1), (3, -7, 2), (-5, 11, -3)] {
+            let env = Environment::from([("x", x), ("y", y), ("z", z)]);
+            for input in inputs {
+                let e = parse(input).unwrap();
+                assert_eq!(compile(&e).run(&env), eval(&e, &env), "{input}");
+            }
+        }
+    }
+
+    #[test]
+    fn runs_repeatedly() {
+        let program = compile(&parse("n * (n + 1) / 2").unwrap());
+        for n in 0..100 {
+            assert_eq!(
+                program.run(&Environment::from([("n", n)])),
+                Ok(n * (n + 1) / 2)
+            );
+        }
+    }
+}
@@ -0,0 +1,452 @@
+//! 式の記号微分。
+//!
+//! 和、差、積、商の法則と、指数が変数によらない累乗の法則を使い、`let` は
+//! 連鎖律で扱う。`if` は枝ごとに微分するので、条件が切り替わる点を除けば
+//! 正しい。0 や 1 になる項はその場で省き、最後に `simplify` で定数を畳み込む。
+
+use std::collections::HashSet;
+
+use thiserror::Error;
+
+use crate::mod_12_5::fold::{fold, Fold};
+use crate::mod_12_5::number::Number;
+use crate::mod_12_5::simplify::simplify;
+use crate::{Expression, Operation};
+
+/// 微分できない理由。
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+pub enum DeriveError {
+    #[error("\"{0}\" is a boolean and has no derivative")]
+    NotNumeric(String),
+    #[error("cannot differentiate \"{0}\": the exponent depends on the variable")]
+    VariableExponent(String),
+}
+
+impl<N: Number> Expression<N> {
+    /// `var` についての導関数。`let` で束縛した名前の導関数は `d_` で始まる
+    /// 新しい名前に束縛するので、結果は `self` と同じ変数で評価できる。
+    pub fn derive(&self, var: &str) -> Result<Expression<N>, DeriveError> {
+        let mut names = Names(HashSet::from([var.to_owned()]));
+        fold(self, &mut names);
+        let mut deriver = Deriver { var, names: names.0, scope: Vec::new() };
+        let (_, derivative) = fold(self, &mut deriver);
+        Ok(simplify(&derivative?))
+    }
+}
+
+/// 式に現れる名前を集める。
+struct Names(HashSet<String>);
+
+impl<N> Fold<N> for Names {
+    type Output = ();
+
+    fn value(&mut self, _: &N) {}
+
+    fn boolean(&mut self, _: bool) {}
+
+    fn var(&mut self, name: &str) {
+        self.0.insert(name.to_owned());
+    }
+
+    fn op(&mut self, _: Operation, (): (), (): ()) {}
+
+    fn neg(&mut self, (): ()) {}
+
+    fn bind(&mut self, name: &str, (): &()) {
+        self.0.insert(name.to_owned());
+    }
+
+    fn let_in(&mut self, _: &str, (): (), (): ()) {}
+
+    fn if_else(&mut self, (): (), (): (), (): ()) {}
+}
+
+type Derived<N> = (Expression<N>, Result<Expression<N>, DeriveError>);
+
+/// 出力は、元の部分式と、その導関数の組。条件式のように導関数が要らない
+/// 部分もあるので、微分の失敗は使われたときに初めてエラーにする。
+struct Deriver<'a, N> {
+    var: &'a str,
+    /// 使用中の名前。新しい名前はこれと重ならないように選ぶ。
+    names: HashSet<String>,
+    /// 今見えている `let` の名前と、その導関数。導関数が定数でなければ、
+    /// それを束縛した名前を参照する `Var`。
+    scope: Vec<(String, Result<Expression<N>, DeriveError>)>,
+}
+
+impl<N: Number> Fold<N> for Deriver<'_, N> {
+    type Output = Derived<N>;
+
+    fn value(&mut self, v: &N) -> Derived<N> {
+        (Expression::Value(v.clone()), Ok(zero()))
+    }
+
+    fn boolean(&mut self, b: bool) -> Derived<N> {
+        (Expression::Bool(b), Err(DeriveError::NotNumeric(b.to_string())))
+    }
+
+    fn var(&mut self, name: &str) -> Derived<N> {
+        let derivative = match self.scope.iter().rev().find(|(n, _)| n == name) {
+            Some((_, derivative)) => derivative.clone(),
+            None if name == self.var => Ok(Expression::Value(N::one())),
+            None => Ok(zero()),
+        };
+        (Expression::Var(name.to_owned()), derivative)
+    }
+
+    fn op(
+        &mut self,
+        op: Operation,
+        left: Derived<N>,
+        right: Derived<N>,
+    ) -> Derived<N> {
+        let (l, dl) = left;
+        let (r, dr) = right;
+        if op.is_comparison() {
+            let e = binary(op, l, r);
+            let text = e.to_string();
+            return (e, Err(DeriveError::NotNumeric(text)));
+        }
+        // 部分式は導関数の規則が使うときだけ複製し、元の式へはそのまま移す。
+        let derivative = match (dl, dr) {
+            (Ok(dl), Ok(dr)) => derive_op(op, &l, &r, dl, dr).ok_or(None),
+            (Err(err), _) | (_, Err(err)) => Err(Some(err)),
+        };
+        let e = binary(op, l, r);
+        let derivative = derivative.map_err(|err| {
+            err.unwrap_or_else(|| DeriveError::VariableExponent(e.to_string()))
+        });
+        (e, derivative)
+    }
+
+    fn neg(&mut self, (operand, derivative): Derived<N>) -> Derived<N> {
+        (Expression::Neg(Box::new(operand)), derivative.map(negation))
+    }
+
+    fn bind(&mut self, name: &str, (_, derivative): &Derived<N>) {
+        let derivative = match derivative {
+            Ok(d @ Expression::Value(_)) => Ok(d.clone()),
+            Ok(_) => Ok(Expression::Var(self.fresh(name))),
+            Err(err) => Err(err.clone()),
+        };
+        self.scope.push((name.to_owned(), derivative));
+    }
+
+    fn let_in(
+        &mut self,
+        name: &str,
+        value: Derived<N>,
+        body: Derived<N>,
+    ) -> Derived<N> {
+        let (_, bound) = self.scope.pop().expect("bound before the body");
+        let (value, dv) = value;
+        let (body, db) = body;
+        let derivative = db.map(|db| {
+            let inner = let_in(name, value.clone(), db);
+            // 値の導関数は外側のスコープで求めるので、`name` の束縛より外に置く。
+            match (&bound, dv) {
+                (Ok(Expression::Var(d)), Ok(dv)) => let_in(d, dv, inner),
+                _ => inner,
+            }
+        });
+        (let_in(name, value, body), derivative)
+    }
+
+    fn if_else(
+        &mut self,
+        cond: Derived<N>,
+        then: Derived<N>,
+        otherwise: Derived<N>,
+    ) -> Derived<N> {
+        let (cond, _) = cond;
+        let (then, dthen) = then;
+        let (otherwise, dotherwise) = otherwise;
+        let derivative = dthen.and_then(|dthen| {
+            Ok(Expression::If {
+                cond: Box::new(cond.clone()),
+                then: Box::new(dthen),
+                otherwise: Box::new(dotherwise?),
+            })
+        });
+        let e = Expression::If {
+            cond: Box::new(cond),
+            then: Box::new(then),
+            otherwise: Box::new(otherwise),
+        };
+        (e, derivative)
+    }
+}
+
+impl<N> Deriver<'_, N> {
+    /// `name` の導関数を束縛する、まだ使われていない名前。
+    fn fresh(&mut self, name: &str) -> String {
+        let mut fresh = format!("d_{name}");
+        while self.names.contains(&fresh) {
+            fresh.push('_');
+        }
+        self.names.insert(fresh.clone());
+        fresh
+    }
+}
+
+/// `l op r` の導関数。指数が変数によれば `None`。
+fn derive_op<N: Number>(
+    op: Operation,
+    l: &Expression<N>,
+    r: &Expression<N>,
+    dl: Expression<N>,
+    dr: Expression<N>,
+) -> Option<Expression<N>> {
+    Some(match op {
+        Operation::Add => sum(dl, dr),
+        Operation::Sub => difference(dl, dr),
+        Operation::Mul => {
+            let (l, r) = (factor(&dr, l), factor(&dl, r));
+            sum(product(dl, r), product(l, dr))
+        }
+        Operation::Div if is_zero(&dr) => {
+            let r = factor(&dl, r);
+            quotient(dl, r)
+        }
+        Operation::Div => {
+            let square = product(r.clone(), r.clone());
+            let (l, r) = (factor(&dr, l), factor(&dl, r));
+            quotient(difference(product(dl, r), product(l, dr)), square)
+        }
+        // `l % r` は `l - r * trunc(l / r)` で、切り捨ての導関数はほとんど
+        // いたるところ 0。
+        Operation::Rem if is_zero(&dr) => dl,
+        Operation::Rem => {
+            let truncated = binary(Operation::Rem, l.clone(), r.clone());
+            let quotient =
+                binary(Operation::Div, difference(l.clone(), truncated), r.clone());
+            difference(dl, product(dr, quotient))
+        }
+        Operation::Pow if !is_zero(&dr) => return None,
+        Operation::Pow if is_zero(r) || is_zero(&dl) => zero(),
+        Operation::Pow => {
+            let exponent =
+                binary(Operation::Sub, r.clone(), Expression::Value(N::one()));
+            product(
+                product(r.clone(), binary(Operation::Pow, l.clone(), exponent)),
+                dl,
+            )
+        }
+        _ => unreachable!("comparisons have no derivative"),
+    })
+}
+
+fn zero<N: Number>() -> Expression<N> {
+    Expression::Value(N::zero())
+}
+
+fn is_zero<N: Number>(e: &Expression<N>) -> bool {
+    matches!(e, Expression::Value(v) if *v == N::zero())
+}
+
+/// 係数 `coefficient` を掛ける相手として `e` を複製する。係数が 0 なら項ごと
+/// 消えるので、複製せずに 0 を返す。
+fn factor<N: Number>(
+    coefficient: &Expression<N>,
+    e: &Expression<N>,
+) -> Expression<N> {
+    if is_zero(coefficient) {
+        zero()
+    } else {
+        e.clone()
+    }
+}
+
+fn is_one<N: Number>(e: &Expression<N>) -> bool {
+    matches!(e, Expression::Value(v) if *v == N::one())
+}
+
+fn binary<N>(
+    op: Operation,
+    left: Expression<N>,
+    right: Expression<N>,
+) -> Expression<N> {
+    Expression::Op { op, left: Box::new(left), right: Box::new(right) }
+}
+
+fn let_in<N>(
+    name: &str,
+    value: Expression<N>,
+    body: Expression<N>,
+) -> Expression<N> {
+    Expression::Let {
+        name: name.to_owned(),
+        value: Box::new(value),
+        body: Box::new(body),
+    }
+}
+
+// 以下は導関数の項を組み立てる。`simplify` と違って、0 倍した項は元の式が
+// エラーになりうるときも消す。導関数は元の式が定義される点でだけ意味を持つ。
+
+fn sum<N: Number>(a: Expression<N>, b: Expression<N>) -> Expression<N> {
+    match (is_zero(&a), is_zero(&b)) {
+        (true, _) => b,
+        (_, true) => a,
+        _ => binary(Operation::Add, a, b),
+    }
+}
+
+fn difference<N: Number>(a: Expression<N>, b: Expression<N>) -> Expression<N> {
+    match (is_zero(&a), is_zero(&b)) {
+        (_, true) => a,
+        (true, _) => negation(b),
+        _ => binary(Operation::Sub, a, b),
+    }
+}
+
+fn product<N: Number>(a: Expression<N>, b: Expression<N>) -> Expression<N> {
+    if is_zero(&a) || is_zero(&b) {
+        zero()
+    } else if is_one(&a) {
+        b
+    } else if is_one(&b) {
+        a
+    } else {
+        binary(Operation::Mul, a, b)
+    }
+}
+
+fn quotient<N: Number>(a: Expression<N>, b: Expression<N>) -> Expression<N> {
+    if is_zero(&a) {
+        zero()
+    } else {
+        binary(Operation::Div, a, b)
+    }
+}
+
+fn negation<N: Number>(a: Expression<N>) -> Expression<N> {
+    if is_zero(&a) {
+        a
+    } else {
+        Expression::Neg(Box::new(a))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::eval;
+    use crate::mod_12_5::environment::Environment;
+    use crate::mod_12_5::parser::parse;
+
+    fn derived(input: &str, var: &str) -> String {
+        parse(input).unwrap().derive(var).unwrap().to_string()
+    }
+
+    #[test]
+    fn rules() {
+        for (input, expected) in [
+            ("7", "0"),
+            ("y", "0"),
+            ("x", "1"),
+            ("x + y - 3", "1"),
+            ("3 * x", "3"),
+            ("x * x", "x + x"),
+            ("x * y", "y"),
+            ("x / y", "1 / y"),
+            ("y / x", "-y / (x * x)"),
+            ("x ^ 3 + 2 * x", "3 * x ^ 2 + 2"),
+            ("x ^ 0", "0"),
+            ("x ^ y", "y * x ^ (y - 1)"),
+            ("-(x * y)", "-y"),
+            ("x % 4", "1"),
+        ] {
+            assert_eq!(derived(input, "x"), expected, "{input}");
+        }
+    }
+
+    #[test]
+    fn let_uses_the_chain_rule() {
+        assert_eq!(derived("let a = 2 in a * x", "x"), "2");
+        assert_eq!(derived("let a = y * 2 in a * x", "x"), "let a = y * 2 in a");
+        assert_eq!(
+            derived("let u = x * x in u * u", "x"),
+            "let d_u = x + x in let u = x * x in d_u * u + u * d_u"
+        );
+        // 束縛した名前が変数を隠せば、本体ではその名前は変数ではない。
+        assert_eq!(derived("let x = 3 in x * y", "x"), "0");
+        // 新しい名前は既存の名前と重ならない。
+        assert_eq!(
+            derived("let u = x * x in u * d_u", "x"),
+            "let d_u_ = x + x in let u = x * x in d_u_ * d_u"
+        );
+    }
+
+    #[test]
+    fn branches_are_derived_separately() {
+        assert_eq!(
+            derived("if x < 0 then -x else x * x", "x"),
+            "if x < 0 then -1 else x + x"
+        );
+        assert_eq!(
+            derived("let neg = x < 0 in if neg then 0 - x else x", "x"),
+            "let neg = x < 0 in if neg then -1 else 1"
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let error = |input: &str| parse(input).unwrap().derive("x").unwrap_err();
+        assert_eq!(error("x < 1"), DeriveError::NotNumeric(String::from("x < 1")));
+        assert_eq!(
+            error("if true then true else x"),
+            DeriveError::NotNumeric(String::from("true"))
+        );
+        assert_eq!(
+            error("2 ^ x"),
+            DeriveError::VariableExponent(String::from("2 ^ x"))
+        );
+        assert_eq!(
+            error("let n = x in y ^ n").to_string(),
+            "cannot differentiate \"y ^ n\": the exponent depends on the variable"
+        );
+    }
+
+    #[test]
+    fn agrees_with_finite_differences() {
+        let inputs = [
+            "x * x * x - 4 * x / (y + 2)",
+            "let r = x * x + y * y in r / (1 + r)",
+            "(x - y) ^ 3 * -x + x ^ -2",
+            "if x > y then x * y else y / x",
+            "let a = x * 2 in let a = a * a + x in a - y * a",
+            "x % 1.5 + x",
+        ];
+        let h = 1e-6;
+        for input in inputs {
+            let e: Expression<f64> = input.parse().unwrap();
+            let derivative = e.derive("x").unwrap();
+            for (x, y) in [(0.7, 0.3), (1.9, -0.4), (-1.2, 2.5)] {
+                let at = |x: f64| {
+                    eval(&e, &Environment::from([("x", x), ("y", y)])).unwrap()
+                };
+                let numeric = (at(x + h) - at(x - h)) / (2.0 * h);
+                let exact =
+                    eval(&derivative, &Environment::from([("x", x), ("y", y)]))
+                        .unwrap();
+                assert!(
+                    (exact - numeric).abs() < 1e-4,
+                    "{input} at {x}: {exact} vs {numeric}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn deep_tree() {
+        // 積の法則は 10 万段の左の因子を複製するが、スタックを使い果たさない。
+        let x = || Expression::Var(String::from("x"));
+        let mut sum = x();
+        for _ in 0..100_000 {
+            sum = sum + Expression::Value(1);
+        }
+        let derivative = (sum * x()).derive("x").unwrap();
+        assert_eq!(eval(&derivative, &Environment::from([("x", 2)])), Ok(100_004));
+    }
+}
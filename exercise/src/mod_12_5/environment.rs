@@ -0,0 +1,63 @@
+use std::collections::HashMap;
+
+use super::number::Number;
+
+/// 式の入力となる、変数名から値への対応。`let` による束縛はここには入らず、
+/// 評価器が自分のスコープで管理する。
+#[derive(Debug)]
+pub struct Environment<N = i64> {
+    vars: HashMap<String, N>,
+}
+
+impl Environment {
+    /// 整数の環境。ほかの型の環境は `Environment::default()` で作る。
+    pub fn new() -> Environment {
+        Environment::default()
+    }
+}
+
+impl<N> Default for Environment<N> {
+    fn default() -> Environment<N> {
+        Environment { vars: HashMap::new() }
+    }
+}
+
+impl<N: Number> Environment<N> {
+    /// `name` を `value` に束縛する。既にあれば上書きする。
+    ///
+    /// `f64` の無限大や NaN のように式の値として扱えない値では panic する。
+    /// `simplify` は変数が値に束縛されているとみなして `x - x` を 0 にするので、
+    /// そうした値を入れると簡約の前後で結果が変わってしまう。
+    pub fn set(&mut self, name: &str, value: N) {
+        assert!(value.is_value(), "{name} = {value} is not a value");
+        self.vars.insert(name.to_owned(), value);
+    }
+
+    pub fn get(&self, name: &str) -> Option<N> {
+        self.vars.get(name).cloned()
+    }
+}
+
+impl<N: Number, const K: usize> From<[(&str, N); K]> for Environment<N> {
+    fn from(vars: [(&str, N); K]) -> Environment<N> {
+        let mut env = Environment::default();
+        for (name, value) in vars {
+            env.set(name, value);
+        }
+        env
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn set_and_get() {
+        let mut env = Environment::from([("x", 1), ("y", 2)]);
+        env.set("x", 10);
+        assert_eq!(env.get("x"), Some(10));
+        assert_eq!(env.get("y"), Some(2));
+        assert_eq!(env.get("z"), None);
+    }
+}
@@ -0,0 +1,237 @@
+//! `Expression` を葉から根へ畳み込む汎用の仕組み。
+//!
+//! 各パス (評価、簡約、コンパイル、表示など) は `Fold` を実装するだけでよく、
+//! 木をたどる処理は `fold` が明示的なスタックで行う。そのため、どれほど深い
+//! 木でもネイティブスタックを使い果たさない。
+
+use crate::{Expression, Operation};
+
+/// 各ノードで呼ばれる処理。子は左から右へ畳み込まれる。`let` は値、`bind`、
+/// 本体の順、`if` は条件、`enter_then`、`then` 節、`enter_else`、`else` 節の順。
+pub trait Fold<N> {
+    type Output;
+
+    fn value(&mut self, v: &N) -> Self::Output;
+
+    fn boolean(&mut self, b: bool) -> Self::Output;
+
+    fn var(&mut self, name: &str) -> Self::Output;
+
+    fn op(
+        &mut self,
+        op: Operation,
+        left: Self::Output,
+        right: Self::Output,
+    ) -> Self::Output;
+
+    fn neg(&mut self, operand: Self::Output) -> Self::Output;
+
+    /// `let` の値を畳み込んだ後、本体に入る前に呼ばれる。スコープを持つパスは
+    /// ここで `name` を束縛し、`let_in` で外す。
+    fn bind(&mut self, _name: &str, _value: &Self::Output) {}
+
+    fn let_in(
+        &mut self,
+        name: &str,
+        value: Self::Output,
+        body: Self::Output,
+    ) -> Self::Output;
+
+    /// 条件を畳み込んだ後、`then` 節に入る前に呼ばれる。
+    fn enter_then(&mut self, _cond: &Self::Output) {}
+
+    /// `then` 節を畳み込んだ後、`else` 節に入る前に呼ばれる。
+    fn enter_else(&mut self, _then: &Self::Output) {}
+
+    fn if_else(
+        &mut self,
+        cond: Self::Output,
+        then: Self::Output,
+        otherwise: Self::Output,
+    ) -> Self::Output;
+}
+
+enum Task<'e, N> {
+    Visit(&'e Expression<N>),
+    Op(Operation),
+    Neg,
+    Bind(&'e str),
+    Let(&'e str),
+    EnterThen,
+    EnterElse,
+    If,
+}
+
+/// `e` を `folder` で畳み込む。
+pub fn fold<N, F: Fold<N> + ?Sized>(e: &Expression<N>, folder: &mut F) -> F::Output {
+    let mut tasks = vec![Task::Visit(e)];
+    let mut outputs = Vec::new();
+    while let Some(task) = tasks.pop() {
+        match task {
+            Task::Visit(Expression::Value(v)) => outputs.push(folder.value(v)),
+            Task::Visit(Expression::Bool(b)) => outputs.push(folder.boolean(*b)),
+            Task::Visit(Expression::Var(name)) => outputs.push(folder.var(name)),
+            Task::Visit(Expression::Op { op, left, right }) => {
+                tasks.push(Task::Op(*op));
+                tasks.push(Task::Visit(right));
+                tasks.push(Task::Visit(left));
+            }
+            Task::Visit(Expression::Neg(operand)) => {
+                tasks.push(Task::Neg);
+                tasks.push(Task::Visit(operand));
+            }
+            Task::Visit(Expression::Let { name, value, body }) => {
+                tasks.push(Task::Let(name));
+                tasks.push(Task::Visit(body));
+                tasks.push(Task::Bind(name));
+                tasks.push(Task::Visit(value));
+            }
+            Task::Visit(Expression::If { cond, then, otherwise }) => {
+                tasks.push(Task::If);
+                tasks.push(Task::Visit(otherwise));
+                tasks.push(Task::EnterElse);
+                tasks.push(Task::Visit(then));
+                tasks.push(Task::EnterThen);
+                tasks.push(Task::Visit(cond));
+            }
+            Task::Op(op) => {
+                let right = outputs.pop().expect("operands are folded first");
+                let left = outputs.pop().expect("operands are folded first");
+                outputs.push(folder.op(op, left, right));
+            }
+            Task::Neg => {
+                let operand = outputs.pop().expect("the operand is folded first");
+                outputs.push(folder.neg(operand));
+            }
+            Task::Bind(name) => folder.bind(name, last(&outputs)),
+            Task::Let(name) => {
+                let body = outputs.pop().expect("the body is folded first");
+                let value = outputs.pop().expect("the value is folded first");
+                outputs.push(folder.let_in(name, value, body));
+            }
+            Task::EnterThen => folder.enter_then(last(&outputs)),
+            Task::EnterElse => folder.enter_else(last(&outputs)),
+            Task::If => {
+                let otherwise =
+                    outputs.pop().expect("the branches are folded first");
+                let then = outputs.pop().expect("the branches are folded first");
+                let cond = outputs.pop().expect("the condition is folded first");
+                outputs.push(folder.if_else(cond, then, otherwise));
+            }
+        }
+    }
+    outputs.pop().expect("every expression folds to one output")
+}
+
+fn last<T>(outputs: &[T]) -> &T {
+    outputs.last().expect("the previous child is folded first")
+}
+
+/// 式のノード数を数える。
+pub struct NodeCount;
+
+impl<N> Fold<N> for NodeCount {
+    type Output = usize;
+
+    fn value(&mut self, _: &N) -> usize {
+        1
+    }
+
+    fn boolean(&mut self, _: bool) -> usize {
+        1
+    }
+
+    fn var(&mut self, _: &str) -> usize {
+        1
+    }
+
+    fn op(&mut self, _: Operation, left: usize, right: usize) -> usize {
+        1 + left + right
+    }
+
+    fn neg(&mut self, operand: usize) -> usize {
+        1 + operand
+    }
+
+    fn let_in(&mut self, _: &str, value: usize, body: usize) -> usize {
+        1 + value + body
+    }
+
+    fn if_else(&mut self, cond: usize, then: usize, otherwise: usize) -> usize {
+        1 + cond + then + otherwise
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mod_12_5::parser::parse;
+
+    /// 呼ばれた順に記録する。
+    struct Trace(Vec<String>);
+
+    impl Fold<i64> for Trace {
+        type Output = ();
+
+        fn value(&mut self, v: &i64) {
+            self.0.push(v.to_string());
+        }
+
+        fn boolean(&mut self, b: bool) {
+            self.0.push(b.to_string());
+        }
+
+        fn var(&mut self, name: &str) {
+            self.0.push(name.to_owned());
+        }
+
+        fn op(&mut self, op: Operation, (): (), (): ()) {
+            self.0.push(format!("{op:?}"));
+        }
+
+        fn neg(&mut self, (): ()) {
+            self.0.push(String::from("Neg"));
+        }
+
+        fn bind(&mut self, name: &str, (): &()) {
+            self.0.push(format!("bind {name}"));
+        }
+
+        fn let_in(&mut self, name: &str, (): (), (): ()) {
+            self.0.push(format!("unbind {name}"));
+        }
+
+        fn enter_then(&mut self, (): &()) {
+            self.0.push(String::from("then"));
+        }
+
+        fn enter_else(&mut self, (): &()) {
+            self.0.push(String::from("else"));
+        }
+
+        fn if_else(&mut self, (): (), (): (), (): ()) {
+            self.0.push(String::from("If"));
+        }
+    }
+
+    #[test]
+    fn order() {
+        let mut trace = Trace(Vec::new());
+        fold(&parse("let x = 1 - 2 in x * y").unwrap(), &mut trace);
+        assert_eq!(
+            trace.0,
+            ["1", "2", "Sub", "bind x", "x", "y", "Mul", "unbind x"]
+        );
+        let mut trace = Trace(Vec::new());
+        fold(&parse("if true then -x else 3").unwrap(), &mut trace);
+        assert_eq!(trace.0, ["true", "then", "x", "Neg", "else", "3", "If"]);
+    }
+
+    #[test]
+    fn count() {
+        assert_eq!(
+            fold(&parse("let x = 1 - 2 in x * y").unwrap(), &mut NodeCount),
+            7
+        );
+    }
+}
@@ -0,0 +1,209 @@
+//! 式の値になる数の型。
+//!
+//! 演算はどれも失敗しうる。ゼロ除算や桁あふれをどう扱うかは型ごとに決める。
+
+use std::fmt;
+
+use crate::EvalError;
+
+pub trait Number: Clone + PartialOrd + fmt::Debug + fmt::Display {
+    fn zero() -> Self;
+
+    fn one() -> Self;
+
+    /// `12`、`-3`、`2.5` のような数値リテラル。表せなければ `None`。
+    fn from_literal(text: &str) -> Option<Self>;
+
+    fn try_add(&self, rhs: &Self) -> Result<Self, EvalError>;
+
+    fn try_sub(&self, rhs: &Self) -> Result<Self, EvalError>;
+
+    fn try_mul(&self, rhs: &Self) -> Result<Self, EvalError>;
+
+    fn try_div(&self, rhs: &Self) -> Result<Self, EvalError>;
+
+    /// `try_div` と対になる余り。符号は `self` に従う。
+    fn try_rem(&self, rhs: &Self) -> Result<Self, EvalError>;
+
+    /// `self` の `exp` 乗。型が表せない指数は `InvalidExponent`。
+    fn try_pow(&self, exp: &Self) -> Result<Self, EvalError>;
+
+    fn try_neg(&self) -> Result<Self, EvalError> {
+        Self::zero().try_sub(self)
+    }
+
+    /// 式の値として扱えるか。`f64` の無限大や NaN は扱えない。
+    fn is_value(&self) -> bool {
+        true
+    }
+}
+
+/// 二乗を繰り返して `base` の `exp` 乗を求める。
+pub fn power<N: Number>(base: &N, mut exp: u64) -> Result<N, EvalError> {
+    let mut result = N::one();
+    let mut base = base.clone();
+    while exp > 0 {
+        if exp % 2 == 1 {
+            result = result.try_mul(&base)?;
+        }
+        exp /= 2;
+        // 最後の二乗は使わないので、それで桁あふれさせない。
+        if exp > 0 {
+            base = base.try_mul(&base)?;
+        }
+    }
+    Ok(result)
+}
+
+/// 64 ビット整数。結果が収まらなければ `Overflow`、除算はゼロ方向に切り捨てる。
+impl Number for i64 {
+    fn zero() -> i64 {
+        0
+    }
+
+    fn one() -> i64 {
+        1
+    }
+
+    fn from_literal(text: &str) -> Option<i64> {
+        text.parse().ok()
+    }
+
+    fn try_add(&self, rhs: &i64) -> Result<i64, EvalError> {
+        self.checked_add(*rhs).ok_or(EvalError::Overflow)
+    }
+
+    fn try_sub(&self, rhs: &i64) -> Result<i64, EvalError> {
+        self.checked_sub(*rhs).ok_or(EvalError::Overflow)
+    }
+
+    fn try_mul(&self, rhs: &i64) -> Result<i64, EvalError> {
+        self.checked_mul(*rhs).ok_or(EvalError::Overflow)
+    }
+
+    fn try_div(&self, rhs: &i64) -> Result<i64, EvalError> {
+        if *rhs == 0 {
+            return Err(EvalError::DivisionByZero);
+        }
+        // i64::MIN / -1 は i64 に収まらない。
+        self.checked_div(*rhs).ok_or(EvalError::Overflow)
+    }
+
+    fn try_rem(&self, rhs: &i64) -> Result<i64, EvalError> {
+        if *rhs == 0 {
+            return Err(EvalError::DivisionByZero);
+        }
+        self.checked_rem(*rhs).ok_or(EvalError::Overflow)
+    }
+
+    /// 指数は 0 以上に限る。
+    fn try_pow(&self, exp: &i64) -> Result<i64, EvalError> {
+        let Ok(exp) = u64::try_from(*exp) else {
+            return Err(EvalError::InvalidExponent);
+        };
+        power(self, exp)
+    }
+}
+
+/// 倍精度浮動小数点数。丸め誤差は許すが、無限大や NaN は値として扱わない。
+/// ゼロでの除算は `DivisionByZero`、有限の値から無限大になれば `Overflow`。
+impl Number for f64 {
+    fn zero() -> f64 {
+        0.0
+    }
+
+    fn one() -> f64 {
+        1.0
+    }
+
+    fn from_literal(text: &str) -> Option<f64> {
+        text.parse().ok().filter(|v: &f64| v.is_finite())
+    }
+
+    fn is_value(&self) -> bool {
+        self.is_finite()
+    }
+
+    fn try_add(&self, rhs: &f64) -> Result<f64, EvalError> {
+        finite(self + rhs)
+    }
+
+    fn try_sub(&self, rhs: &f64) -> Result<f64, EvalError> {
+        finite(self - rhs)
+    }
+
+    fn try_mul(&self, rhs: &f64) -> Result<f64, EvalError> {
+        finite(self * rhs)
+    }
+
+    fn try_div(&self, rhs: &f64) -> Result<f64, EvalError> {
+        if *rhs == 0.0 {
+            return Err(EvalError::DivisionByZero);
+        }
+        finite(self / rhs)
+    }
+
+    fn try_rem(&self, rhs: &f64) -> Result<f64, EvalError> {
+        if *rhs == 0.0 {
+            return Err(EvalError::DivisionByZero);
+        }
+        finite(self % rhs)
+    }
+
+    /// 負の数の非整数乗のように実数にならないものは `InvalidExponent`、
+    /// 0 の負の乗は `DivisionByZero`。
+    fn try_pow(&self, exp: &f64) -> Result<f64, EvalError> {
+        if *self == 0.0 && *exp < 0.0 {
+            return Err(EvalError::DivisionByZero);
+        }
+        let v = self.powf(*exp);
+        if v.is_nan() {
+            return Err(EvalError::InvalidExponent);
+        }
+        finite(v)
+    }
+}
+
+fn finite(v: f64) -> Result<f64, EvalError> {
+    if v.is_finite() {
+        Ok(v)
+    } else {
+        Err(EvalError::Overflow)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn integers() {
+        assert_eq!(7i64.try_div(&2), Ok(3));
+        assert_eq!((-7i64).try_div(&2), Ok(-3));
+        assert_eq!(1i64.try_div(&0), Err(EvalError::DivisionByZero));
+        assert_eq!(i64::MIN.try_div(&-1), Err(EvalError::Overflow));
+        assert_eq!(i64::MAX.try_add(&1), Err(EvalError::Overflow));
+        assert_eq!(i64::from_literal("2.5"), None);
+        assert_eq!((-7i64).try_rem(&2), Ok(-1));
+        assert_eq!(7i64.try_rem(&0), Err(EvalError::DivisionByZero));
+        assert_eq!((-2i64).try_pow(&63), Ok(i64::MIN));
+        assert_eq!(2i64.try_pow(&63), Err(EvalError::Overflow));
+        assert_eq!(1i64.try_pow(&i64::MAX), Ok(1));
+        assert_eq!(2i64.try_pow(&-1), Err(EvalError::InvalidExponent));
+        assert_eq!(i64::MIN.try_neg(), Err(EvalError::Overflow));
+    }
+
+    #[test]
+    fn floats() {
+        assert_eq!(7.0.try_div(&2.0), Ok(3.5));
+        assert_eq!(1.0.try_div(&0.0), Err(EvalError::DivisionByZero));
+        assert_eq!(1e308.try_mul(&10.0), Err(EvalError::Overflow));
+        assert_eq!(f64::from_literal("0.25"), Some(0.25));
+        assert_eq!(f64::from_literal("1e999"), None);
+        assert_eq!(7.5.try_rem(&2.0), Ok(1.5));
+        assert_eq!(2.0.try_pow(&-1.0), Ok(0.5));
+        assert_eq!((-8.0).try_pow(&0.5), Err(EvalError::InvalidExponent));
+        assert_eq!(0.0.try_pow(&-1.0), Err(EvalError::DivisionByZero));
+        assert_eq!(10.0.try_pow(&400.0), Err(EvalError::Overflow));
+    }
+}
@@ -0,0 +1,148 @@
+//! Rust の演算子と `expr!` マクロで `Expression` を組み立てる。
+//!
+//! `expr!((3 - 4) * 5)` は `parse("(3 - 4) * 5")` と同じ木を、実行時の解析なしに
+//! 作る。名前は `Var` に、整数と小数のリテラルは `Value` になり、演算子の優先順位と
+//! 結合は Rust のものに従う。ただし `parse` は `-3` を負のリテラル `Value(-3)` と
+//! 読むが、`expr!(-3)` は `Neg(Value(3))` になる。値は同じだが木は違う。
+//!
+//! Rust で同じ意味にならない `^`、比較、`let`、`if` は書けないので、それらを
+//! 含む式は `parse` で作る。`==` と `!=` は Rust の `bool` を返す比較として
+//! 通ってしまうので、マクロがコンパイルエラーにする。
+
+use std::ops::{Add, Div, Mul, Neg, Rem, Sub};
+
+use crate::{Expression, Operation};
+
+impl From<i64> for Expression<i64> {
+    fn from(v: i64) -> Expression<i64> {
+        Expression::Value(v)
+    }
+}
+
+impl From<f64> for Expression<f64> {
+    fn from(v: f64) -> Expression<f64> {
+        Expression::Value(v)
+    }
+}
+
+macro_rules! binary_operator {
+    ($trait:ident, $method:ident, $op:expr) => {
+        impl<N> $trait for Expression<N> {
+            type Output = Expression<N>;
+
+            fn $method(self, rhs: Expression<N>) -> Expression<N> {
+                Expression::Op {
+                    op: $op,
+                    left: Box::new(self),
+                    right: Box::new(rhs),
+                }
+            }
+        }
+    };
+}
+
+binary_operator!(Add, add, Operation::Add);
+binary_operator!(Sub, sub, Operation::Sub);
+binary_operator!(Mul, mul, Operation::Mul);
+binary_operator!(Div, div, Operation::Div);
+binary_operator!(Rem, rem, Operation::Rem);
+
+impl<N> Neg for Expression<N> {
+    type Output = Expression<N>;
+
+    fn neg(self) -> Expression<N> {
+        Expression::Neg(Box::new(self))
+    }
+}
+
+/// Rust の式の書き方で `Expression` を作る。`-3` は負のリテラルではなく
+/// `Neg` になる。
+///
+/// ```ignore
+/// let e: Expression = expr!((price - 5) * qty % 7);
+/// ```
+macro_rules! expr {
+    // 読んだトークンを、演算子を除いて `Expression` に置き換えながら `[...]` に
+    // 積み、最後にそのまま Rust の式として並べる。
+    (@munch [$($out:tt)*]) => { $($out)* };
+    (@munch [$($out:tt)*] ($($inner:tt)*) $($rest:tt)*) => {
+        $crate::mod_12_5::ops::expr!(
+            @munch [$($out)* ($crate::mod_12_5::ops::expr!($($inner)*))] $($rest)*
+        )
+    };
+    (@munch [$($out:tt)*] true $($rest:tt)*) => {
+        $crate::mod_12_5::ops::expr!(
+            @munch [$($out)* $crate::Expression::Bool(true)] $($rest)*
+        )
+    };
+    (@munch [$($out:tt)*] false $($rest:tt)*) => {
+        $crate::mod_12_5::ops::expr!(
+            @munch [$($out)* $crate::Expression::Bool(false)] $($rest)*
+        )
+    };
+    // `literal` は `-1` も受け付け、`- x` を読みかけると失敗するので、先に
+    // `-` を演算子として読む。
+    (@munch [$($out:tt)*] - $($rest:tt)*) => {
+        $crate::mod_12_5::ops::expr!(@munch [$($out)* -] $($rest)*)
+    };
+    (@munch [$($out:tt)*] $v:literal $($rest:tt)*) => {
+        $crate::mod_12_5::ops::expr!(
+            @munch [$($out)* $crate::Expression::from($v)] $($rest)*
+        )
+    };
+    (@munch [$($out:tt)*] $name:ident $($rest:tt)*) => {
+        $crate::mod_12_5::ops::expr!(
+            @munch [$($out)* $crate::Expression::Var(String::from(stringify!($name)))] $($rest)*
+        )
+    };
+    // `Expression` の `PartialEq` で `bool` になってしまうので、比較の式として
+    // 読まれると思って書いたものを通さない。
+    (@munch [$($out:tt)*] == $($rest:tt)*) => {
+        compile_error!("expr! cannot build comparisons; use parse for `==`")
+    };
+    (@munch [$($out:tt)*] != $($rest:tt)*) => {
+        compile_error!("expr! cannot build comparisons; use parse for `!=`")
+    };
+    (@munch [$($out:tt)*] $op:tt $($rest:tt)*) => {
+        $crate::mod_12_5::ops::expr!(@munch [$($out)* $op] $($rest)*)
+    };
+    ($($tokens:tt)+) => { $crate::mod_12_5::ops::expr!(@munch [] $($tokens)+) };
+}
+
+pub(crate) use expr;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mod_12_5::parser::parse;
+
+    #[test]
+    fn operators() {
+        let (x, y) =
+            (Expression::Var(String::from("x")), Expression::Var(String::from("y")));
+        assert_eq!(
+            (x.clone() + Expression::from(2)) * -y.clone() % x.clone() / y - x,
+            parse("(x + 2) * -y % x / y - x").unwrap()
+        );
+    }
+
+    #[test]
+    fn same_tree_as_parse() {
+        assert_eq!(expr!((3 - 4) * 5), parse("(3 - 4) * 5").unwrap());
+        assert_eq!(expr!(8 - 4 - 2), parse("8 - 4 - 2").unwrap());
+        assert_eq!(expr!(1 + 2 * 3 % x), parse("1 + 2 * 3 % x").unwrap());
+        assert_eq!(
+            expr!(rate_2 * (base + (7))),
+            parse("rate_2 * (base + 7)").unwrap()
+        );
+        assert_eq!(expr!(-x - -(y)), parse("-x - -y").unwrap());
+        // 負のリテラルだけは、`parse` と違って `Neg` になる。
+        assert_eq!(expr!(-3), Expression::Neg(Box::new(Expression::Value(3))));
+        assert_eq!(parse("-3").unwrap(), Expression::Value(-3));
+        assert_eq!(expr!(true), Expression::<i64>::Bool(true));
+        assert_eq!(
+            expr!(0.5 * x - 2.25),
+            "0.5 * x - 2.25".parse::<Expression<f64>>().unwrap()
+        );
+    }
+}
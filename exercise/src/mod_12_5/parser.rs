@@ -0,0 +1,687 @@
+//! 中置記法の文字列を `Expression` に変換するパーサー。
+//!
+//! 文法は次のとおり。`^` は右結合、比較は連ねられず、ほかの二項演算子は
+//! 左結合。
+//!
+//! ```text
+//! expr       = "let" 名前 "=" expr "in" expr
+//!            | "if" expr "then" expr "else" expr
+//!            | comparison
+//! comparison = sum (("==" | "!=" | "<" | "<=" | ">" | ">=") sum)?
+//! sum        = term (("+" | "-") term)*
+//! term       = unary (("*" | "/" | "%") unary)*
+//! unary      = "-" unary | power
+//! power      = primary ("^" unary)?
+//! primary    = 数値 | 名前 | "true" | "false" | "(" expr ")"
+//! ```
+//!
+//! 数値は `12` や `2.5` のように書く。その数値を表せるかどうかは、式の値の型
+//! による。
+//!
+//! 名前は英字か `_` で始まり、英数字と `_` が続く。`let`、`in`、`if`、
+//! `then`、`else`、`true`、`false` は名前に使えない。
+//!
+//! 再帰下降で読むので、括弧、`let`、`if`、単項マイナス、`^` の入れ子は
+//! `MAX_DEPTH` 段までに限る。それより深い式はスタックを使い果たす前にエラーに
+//! する。
+
+use std::fmt;
+use std::iter::Peekable;
+use std::str::{CharIndices, FromStr};
+
+use thiserror::Error;
+
+use super::number::Number;
+use crate::{Expression, Operation};
+
+/// 解析エラー。`pos` は入力の先頭から数えたバイト位置。
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ParseError {
+    #[error("unexpected character '{ch}' at position {pos}")]
+    InvalidCharacter { ch: char, pos: usize },
+    #[error("unexpected \"{token}\" at position {pos}")]
+    UnexpectedToken { token: String, pos: usize },
+    #[error("unexpected end of input at position {pos}")]
+    UnexpectedEnd { pos: usize },
+    #[error("number \"{text}\" at position {pos} cannot be represented")]
+    InvalidNumber { text: String, pos: usize },
+    #[error("parenthesis at position {pos} is never closed")]
+    UnclosedParen { pos: usize },
+    #[error("nesting too deep at position {pos}")]
+    TooDeep { pos: usize },
+}
+
+/// 入れ子の深さの上限。テストのスレッドの小さなスタックでも余裕がある。
+const MAX_DEPTH: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token<'a> {
+    Number(&'a str),
+    Name(&'a str),
+    Let,
+    In,
+    If,
+    Then,
+    Else,
+    True,
+    False,
+    Equals,
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Percent,
+    Caret,
+    EqEq,
+    NotEq,
+    Less,
+    LessEq,
+    Greater,
+    GreaterEq,
+    LParen,
+    RParen,
+}
+
+impl fmt::Display for Token<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Number(digits) => write!(f, "{digits}"),
+            Token::Name(name) => write!(f, "{name}"),
+            Token::Let => write!(f, "let"),
+            Token::In => write!(f, "in"),
+            Token::If => write!(f, "if"),
+            Token::Then => write!(f, "then"),
+            Token::Else => write!(f, "else"),
+            Token::True => write!(f, "true"),
+            Token::False => write!(f, "false"),
+            Token::Equals => write!(f, "="),
+            Token::Plus => write!(f, "+"),
+            Token::Minus => write!(f, "-"),
+            Token::Star => write!(f, "*"),
+            Token::Slash => write!(f, "/"),
+            Token::Percent => write!(f, "%"),
+            Token::Caret => write!(f, "^"),
+            Token::EqEq => write!(f, "=="),
+            Token::NotEq => write!(f, "!="),
+            Token::Less => write!(f, "<"),
+            Token::LessEq => write!(f, "<="),
+            Token::Greater => write!(f, ">"),
+            Token::GreaterEq => write!(f, ">="),
+            Token::LParen => write!(f, "("),
+            Token::RParen => write!(f, ")"),
+        }
+    }
+}
+
+/// 入力をトークンと、その開始位置の列に分割する。
+fn tokenize(input: &str) -> Result<Vec<(Token<'_>, usize)>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+    while let Some((pos, ch)) = chars.next() {
+        let token = match ch {
+            c if c.is_whitespace() => continue,
+            '+' => Token::Plus,
+            '-' => Token::Minus,
+            '*' => Token::Star,
+            '/' => Token::Slash,
+            '%' => Token::Percent,
+            '^' => Token::Caret,
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            '=' if chars.next_if(|&(_, c)| c == '=').is_some() => Token::EqEq,
+            '=' => Token::Equals,
+            '!' if chars.next_if(|&(_, c)| c == '=').is_some() => Token::NotEq,
+            '<' if chars.next_if(|&(_, c)| c == '=').is_some() => Token::LessEq,
+            '<' => Token::Less,
+            '>' if chars.next_if(|&(_, c)| c == '=').is_some() => Token::GreaterEq,
+            '>' => Token::Greater,
+            c if c.is_ascii_digit() => {
+                let mut end = scan(&mut chars, pos, |c| c.is_ascii_digit());
+                let rest = &input[end..];
+                if rest.starts_with('.')
+                    && rest[1..].starts_with(|c: char| c.is_ascii_digit())
+                {
+                    chars.next();
+                    end = scan(&mut chars, end, |c| c.is_ascii_digit());
+                }
+                Token::Number(&input[pos..end])
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let end =
+                    scan(&mut chars, pos, |c| c.is_ascii_alphanumeric() || c == '_');
+                match &input[pos..end] {
+                    "let" => Token::Let,
+                    "in" => Token::In,
+                    "if" => Token::If,
+                    "then" => Token::Then,
+                    "else" => Token::Else,
+                    "true" => Token::True,
+                    "false" => Token::False,
+                    name => Token::Name(name),
+                }
+            }
+            ch => return Err(ParseError::InvalidCharacter { ch, pos }),
+        };
+        tokens.push((token, pos));
+    }
+    Ok(tokens)
+}
+
+/// `start` から始まる語の残りを読み進め、語の終わりの位置を返す。
+fn scan(
+    chars: &mut Peekable<CharIndices>,
+    start: usize,
+    part_of_word: impl Fn(char) -> bool,
+) -> usize {
+    let mut end = start + 1;
+    while let Some(&(i, c)) = chars.peek() {
+        if !part_of_word(c) {
+            break;
+        }
+        end = i + 1;
+        chars.next();
+    }
+    end
+}
+
+struct Parser<'a> {
+    tokens: Vec<(Token<'a>, usize)>,
+    next: usize,
+    /// 入力の長さ。入力が途中で終わったときの位置として使う。
+    end: usize,
+    /// 今の入れ子の深さ。
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<Token<'a>> {
+        self.tokens.get(self.next).map(|&(token, _)| token)
+    }
+
+    /// 次のトークンの位置。トークンが残っていなければ入力の末尾。
+    fn pos(&self) -> usize {
+        self.tokens.get(self.next).map_or(self.end, |&(_, pos)| pos)
+    }
+
+    fn advance(&mut self) -> Option<(Token<'a>, usize)> {
+        let token = self.tokens.get(self.next).copied();
+        self.next += 1;
+        token
+    }
+
+    fn unexpected(&self) -> ParseError {
+        match self.tokens.get(self.next) {
+            Some(&(token, pos)) => {
+                ParseError::UnexpectedToken { token: token.to_string(), pos }
+            }
+            None => ParseError::UnexpectedEnd { pos: self.end },
+        }
+    }
+
+    /// 次のトークンが `expected` なら読み進める。そうでなければエラー。
+    fn expect(&mut self, expected: Token) -> Result<(), ParseError> {
+        if self.peek() != Some(expected) {
+            return Err(self.unexpected());
+        }
+        self.advance();
+        Ok(())
+    }
+
+    /// 入れ子を 1 段深くして `parse` で読む。深すぎれば、次のトークンの位置で
+    /// `TooDeep`。
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<T, ParseError> {
+        if self.depth == MAX_DEPTH {
+            return Err(ParseError::TooDeep { pos: self.pos() });
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn expr<N: Number>(&mut self) -> Result<Expression<N>, ParseError> {
+        self.nested(|parser| match parser.peek() {
+            Some(Token::Let) => parser.let_in(),
+            Some(Token::If) => parser.if_else(),
+            _ => parser.comparison(),
+        })
+    }
+
+    fn let_in<N: Number>(&mut self) -> Result<Expression<N>, ParseError> {
+        self.advance();
+        let Some(Token::Name(name)) = self.peek() else {
+            return Err(self.unexpected());
+        };
+        self.advance();
+        self.expect(Token::Equals)?;
+        let value = self.expr()?;
+        self.expect(Token::In)?;
+        let body = self.expr()?;
+        Ok(Expression::Let {
+            name: name.to_owned(),
+            value: Box::new(value),
+            body: Box::new(body),
+        })
+    }
+
+    fn if_else<N: Number>(&mut self) -> Result<Expression<N>, ParseError> {
+        self.advance();
+        let cond = self.expr()?;
+        self.expect(Token::Then)?;
+        let then = self.expr()?;
+        self.expect(Token::Else)?;
+        let otherwise = self.expr()?;
+        Ok(Expression::If {
+            cond: Box::new(cond),
+            then: Box::new(then),
+            otherwise: Box::new(otherwise),
+        })
+    }
+
+    /// `1 < x < 3` のように比較を連ねると、2 つ目の比較演算子でエラーになる。
+    fn comparison<N: Number>(&mut self) -> Result<Expression<N>, ParseError> {
+        let left = self.sum()?;
+        let op = match self.peek() {
+            Some(Token::EqEq) => Operation::Eq,
+            Some(Token::NotEq) => Operation::Ne,
+            Some(Token::Less) => Operation::Lt,
+            Some(Token::LessEq) => Operation::Le,
+            Some(Token::Greater) => Operation::Gt,
+            Some(Token::GreaterEq) => Operation::Ge,
+            _ => return Ok(left),
+        };
+        self.advance();
+        let right = self.sum()?;
+        Ok(Expression::Op { op, left: Box::new(left), right: Box::new(right) })
+    }
+
+    fn sum<N: Number>(&mut self) -> Result<Expression<N>, ParseError> {
+        let mut left = self.term()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Plus) => Operation::Add,
+                Some(Token::Minus) => Operation::Sub,
+                _ => return Ok(left),
+            };
+            self.advance();
+            let right = self.term()?;
+            left =
+                Expression::Op { op, left: Box::new(left), right: Box::new(right) };
+        }
+    }
+
+    fn term<N: Number>(&mut self) -> Result<Expression<N>, ParseError> {
+        let mut left = self.unary()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Star) => Operation::Mul,
+                Some(Token::Slash) => Operation::Div,
+                Some(Token::Percent) => Operation::Rem,
+                _ => return Ok(left),
+            };
+            self.advance();
+            let right = self.unary()?;
+            left =
+                Expression::Op { op, left: Box::new(left), right: Box::new(right) };
+        }
+    }
+
+    /// 負の数値リテラルは `Value` に、それ以外の単項マイナスは `Neg` にする。
+    /// `-2 ^ 2` は `-(2 ^ 2)` なので、後ろに `^` が続く数値はリテラルにしない。
+    fn unary<N: Number>(&mut self) -> Result<Expression<N>, ParseError> {
+        if self.peek() != Some(Token::Minus) {
+            return self.power();
+        }
+        let (_, pos) = self.advance().expect("peeked a minus");
+        if let Some(Token::Number(digits)) = self.peek() {
+            if self.tokens.get(self.next + 1).map(|&(token, _)| token)
+                != Some(Token::Caret)
+            {
+                self.advance();
+                return number(&format!("-{digits}"), pos);
+            }
+        }
+        let operand = self.nested(|parser| parser.unary())?;
+        Ok(Expression::Neg(Box::new(operand)))
+    }
+
+    /// 右辺を `unary` で読むので、`2 ^ 3 ^ 2` は `2 ^ (3 ^ 2)` になり、
+    /// `2 ^ -1` とも書ける。
+    fn power<N: Number>(&mut self) -> Result<Expression<N>, ParseError> {
+        let base = self.primary()?;
+        if self.peek() != Some(Token::Caret) {
+            return Ok(base);
+        }
+        self.advance();
+        let exp = self.nested(|parser| parser.unary())?;
+        Ok(Expression::Op {
+            op: Operation::Pow,
+            left: Box::new(base),
+            right: Box::new(exp),
+        })
+    }
+
+    fn primary<N: Number>(&mut self) -> Result<Expression<N>, ParseError> {
+        match self.peek() {
+            Some(Token::Number(digits)) => {
+                let pos = self.pos();
+                self.advance();
+                number(digits, pos)
+            }
+            Some(Token::Name(name)) => {
+                self.advance();
+                Ok(Expression::Var(name.to_owned()))
+            }
+            Some(Token::True) => {
+                self.advance();
+                Ok(Expression::Bool(true))
+            }
+            Some(Token::False) => {
+                self.advance();
+                Ok(Expression::Bool(false))
+            }
+            Some(Token::LParen) => {
+                if let Some(fraction) = self.fraction() {
+                    return Ok(fraction);
+                }
+                let open = self.pos();
+                self.advance();
+                let inner = self.expr()?;
+                match self.peek() {
+                    Some(Token::RParen) => {
+                        self.advance();
+                        Ok(inner)
+                    }
+                    None => Err(ParseError::UnclosedParen { pos: open }),
+                    Some(_) => Err(self.unexpected()),
+                }
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    /// `(7/2)` や `(-7/2)` を `7/2` という 1 つのリテラルとして読む。有理数は
+    /// この形で表示されるので、読み戻すと同じ木になる。値は除算と変わらない。
+    /// 数の型が読めなければ何も読み進めず、括弧の中の除算として読ませる。
+    fn fraction<N: Number>(&mut self) -> Option<Expression<N>> {
+        let token =
+            |i: usize| self.tokens.get(self.next + i).map(|&(token, _)| token);
+        let (sign, start) =
+            if token(1) == Some(Token::Minus) { ("-", 2) } else { ("", 1) };
+        let (
+            Some(Token::Number(num)),
+            Some(Token::Slash),
+            Some(Token::Number(den)),
+            Some(Token::RParen),
+        ) = (token(start), token(start + 1), token(start + 2), token(start + 3))
+        else {
+            return None;
+        };
+        let value = N::from_literal(&format!("{sign}{num}/{den}"))?;
+        self.next += start + 4;
+        Some(Expression::Value(value))
+    }
+}
+
+fn number<N: Number>(text: &str, pos: usize) -> Result<Expression<N>, ParseError> {
+    N::from_literal(text)
+        .map(Expression::Value)
+        .ok_or_else(|| ParseError::InvalidNumber { text: text.to_owned(), pos })
+}
+
+/// `(3 - 4) * 5 + 10 * 9` のような整数の式を解析する。ほかの数の型では
+/// `"7 / 2".parse::<Expression<f64>>()` のように `FromStr` を使う。
+pub fn parse(input: &str) -> Result<Expression, ParseError> {
+    input.parse()
+}
+
+impl<N: Number> FromStr for Expression<N> {
+    type Err = ParseError;
+
+    fn from_str(input: &str) -> Result<Expression<N>, ParseError> {
+        let mut parser =
+            Parser { tokens: tokenize(input)?, next: 0, end: input.len(), depth: 0 };
+        let expr = parser.expr()?;
+        if parser.peek().is_some() {
+            return Err(parser.unexpected());
+        }
+        Ok(expr)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mod_12_5::bigint::BigInt;
+    use crate::mod_12_5::rational::Rational;
+    use Expression::Value;
+
+    fn op<N>(
+        op: Operation,
+        left: Expression<N>,
+        right: Expression<N>,
+    ) -> Expression<N> {
+        Expression::Op { op, left: Box::new(left), right: Box::new(right) }
+    }
+
+    fn neg<N>(operand: Expression<N>) -> Expression<N> {
+        Expression::Neg(Box::new(operand))
+    }
+
+    #[test]
+    fn precedence_and_associativity() {
+        assert_eq!(
+            parse("1 + 2 * 3"),
+            Ok(op(Operation::Add, Value(1), op(Operation::Mul, Value(2), Value(3))))
+        );
+        assert_eq!(
+            parse("8 - 4 - 2"),
+            Ok(op(Operation::Sub, op(Operation::Sub, Value(8), Value(4)), Value(2)))
+        );
+        assert_eq!(
+            parse("8 / 4 / 2"),
+            Ok(op(Operation::Div, op(Operation::Div, Value(8), Value(4)), Value(2)))
+        );
+    }
+
+    #[test]
+    fn parentheses() {
+        assert_eq!(
+            parse("(1 + 2) * 3"),
+            Ok(op(Operation::Mul, op(Operation::Add, Value(1), Value(2)), Value(3)))
+        );
+        assert_eq!(parse("((7))"), Ok(Value(7)));
+        assert_eq!("( 7 )".parse::<Expression>(), Ok(Value(7)));
+    }
+
+    #[test]
+    fn unary_minus() {
+        assert_eq!(parse("-5"), Ok(Value(-5)));
+        assert_eq!(parse("-9223372036854775808"), Ok(Value(i64::MIN)));
+        assert_eq!(parse("--5"), Ok(neg(Value(-5))));
+        assert_eq!(
+            parse("2 * -(1 + 1)"),
+            Ok(op(
+                Operation::Mul,
+                Value(2),
+                neg(op(Operation::Add, Value(1), Value(1)))
+            ))
+        );
+        assert_eq!(parse("-2 ^ 2"), Ok(neg(op(Operation::Pow, Value(2), Value(2)))));
+        assert_eq!(parse("(-2) ^ 2"), Ok(op(Operation::Pow, Value(-2), Value(2))));
+    }
+
+    #[test]
+    fn power_remainder_and_comparisons() {
+        assert_eq!(
+            parse("2 ^ 3 ^ 2"),
+            Ok(op(Operation::Pow, Value(2), op(Operation::Pow, Value(3), Value(2))))
+        );
+        assert_eq!(parse("2 ^ -1"), Ok(op(Operation::Pow, Value(2), Value(-1))));
+        assert_eq!(
+            parse("7 % 3 * 2"),
+            Ok(op(Operation::Mul, op(Operation::Rem, Value(7), Value(3)), Value(2)))
+        );
+        assert_eq!(
+            parse("1 + 1 >= 2"),
+            Ok(op(Operation::Ge, op(Operation::Add, Value(1), Value(1)), Value(2)))
+        );
+        for (input, expected) in [
+            ("1 == 2", Operation::Eq),
+            ("1 != 2", Operation::Ne),
+            ("1 < 2", Operation::Lt),
+            ("1 <= 2", Operation::Le),
+            ("1 > 2", Operation::Gt),
+        ] {
+            assert_eq!(
+                parse(input),
+                Ok(op(expected, Value(1), Value(2))),
+                "{input}"
+            );
+        }
+        assert_eq!(
+            parse("1 < 2 < 3"),
+            Err(ParseError::UnexpectedToken { token: String::from("<"), pos: 6 })
+        );
+        assert_eq!(
+            parse("1 ! 2"),
+            Err(ParseError::InvalidCharacter { ch: '!', pos: 2 })
+        );
+    }
+
+    #[test]
+    fn booleans_and_if() {
+        assert_eq!(
+            parse("if x > 0 then x else -x"),
+            Ok(Expression::If {
+                cond: Box::new(op(
+                    Operation::Gt,
+                    Expression::Var(String::from("x")),
+                    Value(0)
+                )),
+                then: Box::new(Expression::Var(String::from("x"))),
+                otherwise: Box::new(neg(Expression::Var(String::from("x")))),
+            })
+        );
+        assert_eq!(
+            parse("true == false"),
+            Ok(op(Operation::Eq, Expression::Bool(true), Expression::Bool(false)))
+        );
+        assert_eq!(
+            parse("if true then 1"),
+            Err(ParseError::UnexpectedEnd { pos: 14 })
+        );
+        assert_eq!(
+            parse("let then = 1 in then"),
+            Err(ParseError::UnexpectedToken { token: String::from("then"), pos: 4 })
+        );
+    }
+
+    #[test]
+    fn other_number_types() {
+        assert_eq!(
+            "-2.5 * x".parse::<Expression<f64>>(),
+            Ok(op(Operation::Mul, Value(-2.5), Expression::Var(String::from("x"))))
+        );
+        assert_eq!(
+            "0.1".parse::<Expression<Rational>>(),
+            Ok(Value(Rational::new(1, 10).unwrap()))
+        );
+        // 括弧で囲んだ分数は、有理数なら 1 つのリテラルになる。
+        let rational = |input: &str| input.parse::<Expression<Rational>>();
+        let r = |num, den| Value(Rational::new(num, den).unwrap());
+        assert_eq!(rational("(-7/2)"), Ok(r(-7, 2)));
+        assert_eq!(rational("7/2"), Ok(op(Operation::Div, r(7, 1), r(2, 1))));
+        assert_eq!(rational("(1/0)"), Ok(op(Operation::Div, r(1, 1), r(0, 1))));
+        assert_eq!(parse("(7/2)"), Ok(op(Operation::Div, Value(7), Value(2))));
+        assert_eq!(
+            "-99999999999999999999"
+                .parse::<Expression<BigInt>>()
+                .unwrap()
+                .to_string(),
+            "-99999999999999999999"
+        );
+    }
+
+    #[test]
+    fn variables_and_let() {
+        let var = |name: &str| Expression::Var(name.to_owned());
+        assert_eq!(
+            parse("rate_2 * x"),
+            Ok(op(Operation::Mul, var("rate_2"), var("x")))
+        );
+        assert_eq!(
+            parse("let x = 2 in let y = x in x + y"),
+            Ok(Expression::Let {
+                name: String::from("x"),
+                value: Box::new(Value(2)),
+                body: Box::new(Expression::Let {
+                    name: String::from("y"),
+                    value: Box::new(var("x")),
+                    body: Box::new(op(Operation::Add, var("x"), var("y"))),
+                }),
+            })
+        );
+        assert!(matches!(
+            parse("(let x = 1 in x) + 1"),
+            Ok(Expression::Op { op: Operation::Add, .. })
+        ));
+    }
+
+    #[test]
+    fn deep_nesting() {
+        let parens = |n| format!("{}1{}", "(".repeat(n), ")".repeat(n));
+        assert_eq!(parse(&parens(200)), Ok(Value(1)));
+        assert_eq!(parse(&parens(3000)), Err(ParseError::TooDeep { pos: 256 }));
+        let negations = format!("{}x", "-".repeat(3000));
+        assert_eq!(parse(&negations), Err(ParseError::TooDeep { pos: 256 }));
+        let powers = format!("{}2", "2^".repeat(3000));
+        assert_eq!(parse(&powers), Err(ParseError::TooDeep { pos: 512 }));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            parse("1 + $"),
+            Err(ParseError::InvalidCharacter { ch: '$', pos: 4 })
+        );
+        assert_eq!(parse("1 +"), Err(ParseError::UnexpectedEnd { pos: 3 }));
+        assert_eq!(parse(""), Err(ParseError::UnexpectedEnd { pos: 0 }));
+        assert_eq!(
+            parse("1 2"),
+            Err(ParseError::UnexpectedToken { token: String::from("2"), pos: 2 })
+        );
+        assert_eq!(
+            parse("(1 + 2))"),
+            Err(ParseError::UnexpectedToken { token: String::from(")"), pos: 7 })
+        );
+        assert_eq!(parse("3 * (1 + 2"), Err(ParseError::UnclosedParen { pos: 4 }));
+        assert_eq!(
+            parse("1 + 99999999999999999999"),
+            Err(ParseError::InvalidNumber {
+                text: String::from("99999999999999999999"),
+                pos: 4
+            })
+        );
+        assert_eq!(
+            parse("-2.5"),
+            Err(ParseError::InvalidNumber { text: String::from("-2.5"), pos: 0 })
+        );
+        assert_eq!(
+            parse("2."),
+            Err(ParseError::InvalidCharacter { ch: '.', pos: 1 })
+        );
+        assert_eq!(
+            parse("let in = 1 in 2"),
+            Err(ParseError::UnexpectedToken { token: String::from("in"), pos: 4 })
+        );
+        assert_eq!(parse("let x = 1"), Err(ParseError::UnexpectedEnd { pos: 9 }));
+        assert_eq!(
+            parse("* 2").unwrap_err().to_string(),
+            "unexpected \"*\" at position 0"
+        );
+    }
+}
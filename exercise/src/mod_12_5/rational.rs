@@ -0,0 +1,204 @@
+//! 64 ビットの分子と分母による正確な有理数。
+
+use std::cmp::Ordering;
+use std::fmt;
+
+use super::number::{power, Number};
+use crate::EvalError;
+
+/// 既約分数。分母は常に正。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rational {
+    num: i64,
+    den: i64,
+}
+
+impl Rational {
+    /// `num / den`。分母が 0 か、約分しても 64 ビットに収まらなければ `None`。
+    pub fn new(num: i64, den: i64) -> Option<Rational> {
+        Rational::reduce(num.into(), den.into())
+    }
+
+    fn reduce(mut num: i128, mut den: i128) -> Option<Rational> {
+        if den == 0 {
+            return None;
+        }
+        if den < 0 {
+            num = -num;
+            den = -den;
+        }
+        let g = gcd(num.unsigned_abs(), den.unsigned_abs()) as i128;
+        let (num, den) = if g > 1 { (num / g, den / g) } else { (num, den) };
+        Some(Rational { num: num.try_into().ok()?, den: den.try_into().ok()? })
+    }
+}
+
+fn gcd(mut a: u128, mut b: u128) -> u128 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/// 分母は正なので、分母を払って分子どうしを比べればよい。
+impl Ord for Rational {
+    fn cmp(&self, other: &Rational) -> Ordering {
+        let (a, b, c, d) = self.widen(other);
+        (a * d).cmp(&(c * b))
+    }
+}
+
+impl PartialOrd for Rational {
+    fn partial_cmp(&self, other: &Rational) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// 整数なら `3`、そうでなければ `7/2` のように表示する。
+impl fmt::Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.den == 1 {
+            write!(f, "{}", self.num)
+        } else {
+            write!(f, "{}/{}", self.num, self.den)
+        }
+    }
+}
+
+/// 結果は常に正確。既約にしても分子か分母が 64 ビットに収まらなければ
+/// `Overflow`、ゼロでの除算は `DivisionByZero`。`2.5` のような小数のリテラルは
+/// `5/2` として読み、`7/2` のような分数のリテラルもそのまま読む。累乗の指数は
+/// 整数に限る。
+impl Number for Rational {
+    fn zero() -> Rational {
+        Rational { num: 0, den: 1 }
+    }
+
+    fn one() -> Rational {
+        Rational { num: 1, den: 1 }
+    }
+
+    fn from_literal(text: &str) -> Option<Rational> {
+        if let Some((num, den)) = text.split_once('/') {
+            return Rational::new(num.parse().ok()?, den.parse().ok()?);
+        }
+        let (int, frac) = text.split_once('.').unwrap_or((text, ""));
+        let den = 10i64.checked_pow(frac.len().try_into().ok()?)?;
+        let num: i64 = format!("{int}{frac}").parse().ok()?;
+        Rational::new(num, den)
+    }
+
+    fn try_add(&self, rhs: &Rational) -> Result<Rational, EvalError> {
+        let (a, b, c, d) = self.widen(rhs);
+        Rational::reduce(a * d + c * b, b * d).ok_or(EvalError::Overflow)
+    }
+
+    fn try_sub(&self, rhs: &Rational) -> Result<Rational, EvalError> {
+        let (a, b, c, d) = self.widen(rhs);
+        Rational::reduce(a * d - c * b, b * d).ok_or(EvalError::Overflow)
+    }
+
+    fn try_mul(&self, rhs: &Rational) -> Result<Rational, EvalError> {
+        let (a, b, c, d) = self.widen(rhs);
+        Rational::reduce(a * c, b * d).ok_or(EvalError::Overflow)
+    }
+
+    fn try_div(&self, rhs: &Rational) -> Result<Rational, EvalError> {
+        if rhs.num == 0 {
+            return Err(EvalError::DivisionByZero);
+        }
+        let (a, b, c, d) = self.widen(rhs);
+        Rational::reduce(a * d, b * c).ok_or(EvalError::Overflow)
+    }
+
+    /// 商を整数に切り捨てたときの余り。`a/b - q * c/d` を通分すると
+    /// `(a*d - q*c*b) / (b*d)` で、分子は `a*d` を `c*b` で割った余りになる。
+    fn try_rem(&self, rhs: &Rational) -> Result<Rational, EvalError> {
+        if rhs.num == 0 {
+            return Err(EvalError::DivisionByZero);
+        }
+        let (a, b, c, d) = self.widen(rhs);
+        Rational::reduce((a * d) % (c * b), b * d).ok_or(EvalError::Overflow)
+    }
+
+    /// 負の指数は逆数の累乗。0 の負の乗は `DivisionByZero`。
+    fn try_pow(&self, exp: &Rational) -> Result<Rational, EvalError> {
+        if exp.den != 1 {
+            return Err(EvalError::InvalidExponent);
+        }
+        let base = if exp.num < 0 { Rational::one().try_div(self)? } else { *self };
+        power(&base, exp.num.unsigned_abs())
+    }
+}
+
+impl Rational {
+    /// 両辺の分子と分母を 128 ビットに広げる。64 ビット同士の積と和は
+    /// 128 ビットに収まる。
+    fn widen(&self, rhs: &Rational) -> (i128, i128, i128, i128) {
+        (self.num.into(), self.den.into(), rhs.num.into(), rhs.den.into())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn r(num: i64, den: i64) -> Rational {
+        Rational::new(num, den).unwrap()
+    }
+
+    #[test]
+    fn normalised() {
+        assert_eq!(r(6, -4), r(-3, 2));
+        assert_eq!((r(6, -4).num, r(6, -4).den), (-3, 2));
+        assert_eq!(Rational::new(1, 0), None);
+        assert_eq!(r(i64::MIN, -2), r(1 << 62, 1));
+        assert_eq!(Rational::new(i64::MIN, -1), None);
+        assert_eq!(r(-7, 2).to_string(), "-7/2");
+        assert_eq!(r(4, 2).to_string(), "2");
+    }
+
+    #[test]
+    fn arithmetic_is_exact() {
+        assert_eq!(r(1, 3).try_add(&r(1, 6)), Ok(r(1, 2)));
+        assert_eq!(r(1, 3).try_sub(&r(1, 2)), Ok(r(-1, 6)));
+        assert_eq!(r(2, 3).try_mul(&r(9, 4)), Ok(r(3, 2)));
+        assert_eq!(r(7, 1).try_div(&r(2, 1)), Ok(r(7, 2)));
+        assert_eq!(
+            r(1, 10).try_mul(&r(3, 1)),
+            Rational::from_literal("0.3").ok_or(EvalError::Overflow)
+        );
+        assert_eq!(
+            r(1, 2).try_div(&Rational::zero()),
+            Err(EvalError::DivisionByZero)
+        );
+        assert_eq!(r(1, i64::MAX).try_mul(&r(1, 2)), Err(EvalError::Overflow));
+        assert_eq!(r(7, 2).try_rem(&r(4, 3)), Ok(r(5, 6)));
+        assert_eq!(r(-7, 2).try_rem(&r(1, 1)), Ok(r(-1, 2)));
+        assert_eq!(r(2, 3).try_pow(&r(-3, 1)), Ok(r(27, 8)));
+        assert_eq!(r(4, 1).try_pow(&r(1, 2)), Err(EvalError::InvalidExponent));
+        assert_eq!(
+            Rational::zero().try_pow(&r(-1, 1)),
+            Err(EvalError::DivisionByZero)
+        );
+    }
+
+    #[test]
+    fn ordering() {
+        assert!(r(1, 3) < r(1, 2));
+        assert!(r(-1, 2) < r(-1, 3));
+        assert!(r(i64::MAX, 2) > r(i64::MAX - 1, 2));
+        assert_eq!(r(2, 4).cmp(&r(1, 2)), Ordering::Equal);
+    }
+
+    #[test]
+    fn literals() {
+        assert_eq!(Rational::from_literal("2.5"), Some(r(5, 2)));
+        assert_eq!(Rational::from_literal("-0.125"), Some(r(-1, 8)));
+        assert_eq!(Rational::from_literal("42"), Some(r(42, 1)));
+        assert_eq!(Rational::from_literal("0.00000000000000000001"), None);
+        assert_eq!(Rational::from_literal("-14/4"), Some(r(-7, 2)));
+        assert_eq!(Rational::from_literal("1/0"), None);
+        assert_eq!(Rational::from_literal("0.5/2"), None);
+    }
+}
@@ -0,0 +1,319 @@
+//! 式を評価する前に小さくする最適化。
+//!
+//! 定数だけの部分式を畳み込み、条件が定数の `if` は選ばれる枝に置き換え、
+//! `x + 0`、`x * 1`、`x * 0`、`x - x` などの恒等式を適用する。実行時にエラーに
+//! なる部分式(ゼロ除算やオーバーフロー、型の誤り)は畳み込まず消しもしないので、
+//! 簡約後の式も同じエラーを返す。ただし、変数はすべて束縛されているものとみなす。
+//! `Environment` は無限大や NaN を受け付けないので、束縛された値は有限である。
+
+use crate::mod_12_5::fold::{fold, Fold};
+use crate::mod_12_5::number::Number;
+use crate::mod_12_5::types::{apply, negate, Scalar};
+use crate::{Expression, Operation};
+
+/// `e` と同じ値になる、できるだけ小さい式を返す。
+pub fn simplify<N: Number>(e: &Expression<N>) -> Expression<N> {
+    fold(e, &mut Simplifier { scope: Vec::new() })
+}
+
+struct Simplifier<N> {
+    /// 今見えている `let` の名前と、その値が定数か、数になるか。
+    scope: Vec<(String, Binding<N>)>,
+}
+
+enum Binding<N> {
+    Constant(Scalar<N>),
+    Number,
+    /// 真偽値かもしれないし、エラーになるかもしれない。
+    Unknown,
+}
+
+impl<N: Number> Fold<N> for Simplifier<N> {
+    type Output = Expression<N>;
+
+    fn value(&mut self, v: &N) -> Expression<N> {
+        Expression::Value(v.clone())
+    }
+
+    fn boolean(&mut self, b: bool) -> Expression<N> {
+        Expression::Bool(b)
+    }
+
+    fn var(&mut self, name: &str) -> Expression<N> {
+        match self.lookup(name) {
+            Some(Binding::Constant(v)) => literal(v.clone()),
+            _ => Expression::Var(name.to_owned()),
+        }
+    }
+
+    fn op(
+        &mut self,
+        op: Operation,
+        left: Expression<N>,
+        right: Expression<N>,
+    ) -> Expression<N> {
+        self.simplify_op(op, left, right)
+    }
+
+    fn neg(&mut self, operand: Expression<N>) -> Expression<N> {
+        if let Some(Ok(v)) = constant(&operand).map(|v| negate(&v)) {
+            return literal(v);
+        }
+        Expression::Neg(Box::new(operand))
+    }
+
+    fn bind(&mut self, name: &str, value: &Expression<N>) {
+        let binding = match constant(value) {
+            Some(v) => Binding::Constant(v),
+            None if self.numeric(value) => Binding::Number,
+            None => Binding::Unknown,
+        };
+        self.scope.push((name.to_owned(), binding));
+    }
+
+    fn let_in(
+        &mut self,
+        name: &str,
+        value: Expression<N>,
+        body: Expression<N>,
+    ) -> Expression<N> {
+        self.scope.pop();
+        match value {
+            // 定数は本体に埋め込み済みなので、束縛はもういらない。
+            Expression::Value(_) | Expression::Bool(_) => body,
+            value => Expression::Let {
+                name: name.to_owned(),
+                value: Box::new(value),
+                body: Box::new(body),
+            },
+        }
+    }
+
+    fn if_else(
+        &mut self,
+        cond: Expression<N>,
+        then: Expression<N>,
+        otherwise: Expression<N>,
+    ) -> Expression<N> {
+        match cond {
+            Expression::Bool(true) => then,
+            Expression::Bool(false) => otherwise,
+            cond => Expression::If {
+                cond: Box::new(cond),
+                then: Box::new(then),
+                otherwise: Box::new(otherwise),
+            },
+        }
+    }
+}
+
+impl<N: Number> Simplifier<N> {
+    fn lookup(&self, name: &str) -> Option<&Binding<N>> {
+        self.scope.iter().rev().find(|(n, _)| n == name).map(|(_, b)| b)
+    }
+
+    fn simplify_op(
+        &self,
+        op: Operation,
+        left: Expression<N>,
+        right: Expression<N>,
+    ) -> Expression<N> {
+        if let (Some(a), Some(b)) = (constant(&left), constant(&right)) {
+            if let Ok(v) = apply(op, &a, &b) {
+                return literal(v);
+            }
+        }
+        let (zero, one) = (N::zero(), N::one());
+        let is =
+            |e: &Expression<N>, n: &N| matches!(e, Expression::Value(v) if v == n);
+        // 残す側が真偽値なら、元の式は型の誤りになるので消せない。
+        let keep = |e: &Expression<N>| self.numeric(e);
+        let vanish = |e: &Expression<N>| self.cannot_fail(e);
+        match op {
+            Operation::Add if is(&right, &zero) && keep(&left) => left,
+            Operation::Add if is(&left, &zero) && keep(&right) => right,
+            Operation::Sub if is(&right, &zero) && keep(&left) => left,
+            Operation::Mul | Operation::Div if is(&right, &one) && keep(&left) => {
+                left
+            }
+            Operation::Mul if is(&left, &one) && keep(&right) => right,
+            Operation::Pow if is(&right, &one) && keep(&left) => left,
+            Operation::Mul
+                if (is(&right, &zero) && vanish(&left))
+                    || (is(&left, &zero) && vanish(&right)) =>
+            {
+                Expression::Value(zero)
+            }
+            Operation::Sub if left == right && vanish(&left) => {
+                Expression::Value(zero)
+            }
+            _ => Expression::Op { op, left: Box::new(left), right: Box::new(right) },
+        }
+    }
+
+    /// 評価すれば数になるか、エラーになることが確かな式か。自由変数は数と
+    /// みなす。`let` と `if` は確かめない。
+    fn numeric(&self, e: &Expression<N>) -> bool {
+        match e {
+            Expression::Value(_) | Expression::Neg(_) => true,
+            Expression::Op { op, .. } => !op.is_comparison(),
+            Expression::Var(name) => match self.lookup(name) {
+                None | Some(Binding::Number) => true,
+                Some(Binding::Constant(v)) => matches!(v, Scalar::Number(_)),
+                Some(Binding::Unknown) => false,
+            },
+            Expression::Bool(_) | Expression::Let { .. } | Expression::If { .. } => {
+                false
+            }
+        }
+    }
+
+    /// 評価してもエラーにならず、数になることが確かな式か。演算は、簡約後に
+    /// 残っていればオーバーフローしうるものとして扱う。
+    fn cannot_fail(&self, e: &Expression<N>) -> bool {
+        match e {
+            Expression::Value(_) | Expression::Var(_) => self.numeric(e),
+            _ => false,
+        }
+    }
+}
+
+fn constant<N: Clone>(e: &Expression<N>) -> Option<Scalar<N>> {
+    match e {
+        Expression::Value(v) => Some(Scalar::Number(v.clone())),
+        Expression::Bool(b) => Some(Scalar::Bool(*b)),
+        _ => None,
+    }
+}
+
+fn literal<N>(v: Scalar<N>) -> Expression<N> {
+    match v {
+        Scalar::Number(n) => Expression::Value(n),
+        Scalar::Bool(b) => Expression::Bool(b),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mod_12_5::environment::Environment;
+    use crate::mod_12_5::parser::parse;
+    use crate::{eval, EvalError};
+
+    fn simplified(input: &str) -> Expression {
+        simplify(&parse(input).unwrap())
+    }
+
+    #[test]
+    fn folds_constants() {
+        assert_eq!(simplified("(3 - 4) * 5 + 10 * 9"), Expression::Value(85));
+        assert_eq!(simplified("x * (2 + 3)"), parse("x * 5").unwrap());
+        assert_eq!(
+            simplified("let k = 2 * 3 in k * x + k"),
+            parse("6 * x + 6").unwrap()
+        );
+    }
+
+    #[test]
+    fn identities() {
+        let x = parse("x").unwrap();
+        for input in [
+            "x + 0",
+            "0 + x",
+            "x - 0",
+            "x * 1",
+            "1 * x",
+            "x / 1",
+            "x * (3 - 2)",
+            "x ^ 1",
+        ] {
+            assert_eq!(simplified(input), x, "{input}");
+        }
+        for input in ["x * 0", "0 * x", "x - x", "x * 0 * y", "x * (y - y)"] {
+            assert_eq!(simplified(input), Expression::Value(0), "{input}");
+        }
+    }
+
+    #[test]
+    fn keeps_runtime_errors() {
+        let env = Environment::from([("x", 4)]);
+        for input in [
+            "1 / 0",
+            "x / 0",
+            "(1 / 0) * 0",
+            "(x / 0) - (x / 0)",
+            "(x / (2 - 2)) * 0",
+            "9223372036854775807 + 1",
+            "(x * 4611686018427387904) * 0",
+            "let y = 1 / 0 in 3",
+            "(1 < 2) + 0",
+            "let b = x > 0 in b * 1",
+            "let b = x > 0 in b - b",
+            "if 1 then 2 else 3",
+        ] {
+            let e = parse(input).unwrap();
+            let expected = eval(&e, &env);
+            assert!(matches!(
+                expected,
+                Err(EvalError::DivisionByZero
+                    | EvalError::Overflow
+                    | EvalError::TypeMismatch { .. })
+            ));
+            assert_eq!(eval(&simplify(&e), &env), expected, "{input}");
+        }
+
+        // 無限大は環境に入らないので、`x * 0` や `x - x` を 0 にしてよい。
+        let infinite =
+            std::panic::catch_unwind(|| Environment::from([("x", f64::INFINITY)]));
+        assert!(infinite.is_err());
+        let env = Environment::from([("x", f64::MAX)]);
+        for input in ["(x * 2) * 0", "(x + x) - (x + x)", "-(x * x) + 0"] {
+            let e: Expression<f64> = input.parse().unwrap();
+            let expected = eval(&e, &env);
+            assert_eq!(expected, Err(EvalError::Overflow), "{input}");
+            assert_eq!(eval(&simplify(&e), &env), expected, "{input}");
+        }
+    }
+
+    #[test]
+    fn booleans_and_if() {
+        assert_eq!(simplified("(2 ^ 10 > 1000) == true"), Expression::Bool(true));
+        assert_eq!(simplified("if 1 < 2 then x else 1 / 0"), parse("x").unwrap());
+        assert_eq!(
+            simplified("let big = 10 > 3 in if big then x * 1 else y"),
+            parse("x").unwrap()
+        );
+        assert_eq!(
+            simplified("if x > 0 then x + 0 else -(3)"),
+            parse("if x > 0 then x else -3").unwrap()
+        );
+    }
+
+    #[test]
+    fn shadowed_let_is_not_substituted() {
+        assert_eq!(
+            simplified("let x = 1 in x + (let x = y in x * 2)"),
+            parse("1 + (let x = y in x * 2)").unwrap()
+        );
+    }
+
+    #[test]
+    fn same_value_as_unsimplified() {
+        let inputs = [
+            "x * 0 + y * 1 - (z - z)",
+            "let a = x + 0 in let b = a * 1 in b - a + 7",
+            "(x - 3) * (y + 0) / (1 * z)",
+            "let x = x + 1 in x * x",
+            "-(-x) * (0 - y)",
+            "let n = x % z in if n == 0 then y else n ^ 2 + 0",
+            "((x > y) == (y > x)) != (x == y)",
+        ];
+        for (x, y, z) in [(0, 0, 1), (3, -7, 2), (-5, 11, -3)] {
+            let env = Environment::from([("x", x), ("y", y), ("z", z)]);
+            for input in inputs {
+                let e = parse(input).unwrap();
+                assert_eq!(eval(&simplify(&e), &env), eval(&e, &env), "{input}");
+            }
+        }
+    }
+}
@@ -0,0 +1,257 @@
+//! 式が返す値の型。
+//!
+//! 値は数か真偽値のどちらか。算術と大小比較は数だけを、`if` の条件は真偽値
+//! だけを受け付け、`==` と `!=` は同じ型どうしを比べる。型が合わなければ
+//! `EvalError::TypeMismatch` になる。
+
+use std::fmt;
+
+use super::fold::{fold, Fold};
+use super::number::Number;
+use crate::{EvalError, Expression, Operation};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Type {
+    Number,
+    Bool,
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::Number => write!(f, "number"),
+            Type::Bool => write!(f, "boolean"),
+        }
+    }
+}
+
+/// 評価の結果になる値。
+#[derive(Debug, Clone, PartialEq)]
+pub enum Scalar<N = i64> {
+    Number(N),
+    Bool(bool),
+}
+
+impl<N> Scalar<N> {
+    pub fn type_of(&self) -> Type {
+        match self {
+            Scalar::Number(_) => Type::Number,
+            Scalar::Bool(_) => Type::Bool,
+        }
+    }
+
+    pub fn number(self) -> Result<N, EvalError> {
+        match self {
+            Scalar::Number(n) => Ok(n),
+            Scalar::Bool(_) => Err(mismatch(Type::Number, Type::Bool)),
+        }
+    }
+
+    pub fn bool(self) -> Result<bool, EvalError> {
+        match self {
+            Scalar::Bool(b) => Ok(b),
+            Scalar::Number(_) => Err(mismatch(Type::Bool, Type::Number)),
+        }
+    }
+}
+
+impl<N: fmt::Display> fmt::Display for Scalar<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Scalar::Number(n) => write!(f, "{n}"),
+            Scalar::Bool(b) => write!(f, "{b}"),
+        }
+    }
+}
+
+fn mismatch(expected: Type, found: Type) -> EvalError {
+    EvalError::TypeMismatch { expected, found }
+}
+
+/// `op` の両辺の型から結果の型を求める。
+pub fn op_type(op: Operation, left: Type, right: Type) -> Result<Type, EvalError> {
+    match op {
+        Operation::Eq | Operation::Ne => {
+            if left != right {
+                return Err(mismatch(left, right));
+            }
+            Ok(Type::Bool)
+        }
+        _ => {
+            for t in [left, right] {
+                if t != Type::Number {
+                    return Err(mismatch(Type::Number, t));
+                }
+            }
+            Ok(if op.is_comparison() { Type::Bool } else { Type::Number })
+        }
+    }
+}
+
+/// 評価せずに `e` の型を調べる。自由変数は数とみなす。評価では選ばれなかった
+/// 枝の型の誤りは見逃されるが、ここでは両方の枝を調べる。
+pub fn type_of<N>(e: &Expression<N>) -> Result<Type, EvalError> {
+    fold(e, &mut TypeChecker { scope: Vec::new() })
+}
+
+struct TypeChecker {
+    /// 今見えている `let` の名前と型。
+    scope: Vec<(String, Type)>,
+}
+
+impl<N> Fold<N> for TypeChecker {
+    type Output = Result<Type, EvalError>;
+
+    fn value(&mut self, _: &N) -> Self::Output {
+        Ok(Type::Number)
+    }
+
+    fn boolean(&mut self, _: bool) -> Self::Output {
+        Ok(Type::Bool)
+    }
+
+    fn var(&mut self, name: &str) -> Self::Output {
+        Ok(self
+            .scope
+            .iter()
+            .rev()
+            .find(|(n, _)| n == name)
+            .map_or(Type::Number, |&(_, t)| t))
+    }
+
+    fn op(
+        &mut self,
+        op: Operation,
+        left: Self::Output,
+        right: Self::Output,
+    ) -> Self::Output {
+        op_type(op, left?, right?)
+    }
+
+    fn neg(&mut self, operand: Self::Output) -> Self::Output {
+        op_type(Operation::Sub, Type::Number, operand?)
+    }
+
+    fn bind(&mut self, name: &str, value: &Self::Output) {
+        // 値の型が誤っていれば `let_in` でそのエラーを返すので、ここでは仮に数とする。
+        let t = value.as_ref().map_or(Type::Number, |&t| t);
+        self.scope.push((name.to_owned(), t));
+    }
+
+    fn let_in(
+        &mut self,
+        _: &str,
+        value: Self::Output,
+        body: Self::Output,
+    ) -> Self::Output {
+        self.scope.pop();
+        value?;
+        body
+    }
+
+    fn if_else(
+        &mut self,
+        cond: Self::Output,
+        then: Self::Output,
+        otherwise: Self::Output,
+    ) -> Self::Output {
+        let cond = cond?;
+        if cond != Type::Bool {
+            return Err(mismatch(Type::Bool, cond));
+        }
+        let (then, otherwise) = (then?, otherwise?);
+        if then != otherwise {
+            return Err(mismatch(then, otherwise));
+        }
+        Ok(then)
+    }
+}
+
+/// 2 つの値に演算を適用する。ゼロ除算や桁あふれの扱いは `N` による。
+pub fn apply<N: Number>(
+    op: Operation,
+    left: &Scalar<N>,
+    right: &Scalar<N>,
+) -> Result<Scalar<N>, EvalError> {
+    op_type(op, left.type_of(), right.type_of())?;
+    let (a, b) = match (left, right) {
+        (Scalar::Number(a), Scalar::Number(b)) => (a, b),
+        (Scalar::Bool(a), Scalar::Bool(b)) => {
+            return Ok(Scalar::Bool((a == b) == (op == Operation::Eq)))
+        }
+        _ => unreachable!("op_type rejects mixed operands"),
+    };
+    let number = match op {
+        Operation::Add => a.try_add(b),
+        Operation::Sub => a.try_sub(b),
+        Operation::Mul => a.try_mul(b),
+        Operation::Div => a.try_div(b),
+        Operation::Rem => a.try_rem(b),
+        Operation::Pow => a.try_pow(b),
+        Operation::Eq => return Ok(Scalar::Bool(a == b)),
+        Operation::Ne => return Ok(Scalar::Bool(a != b)),
+        Operation::Lt => return Ok(Scalar::Bool(a < b)),
+        Operation::Le => return Ok(Scalar::Bool(a <= b)),
+        Operation::Gt => return Ok(Scalar::Bool(a > b)),
+        Operation::Ge => return Ok(Scalar::Bool(a >= b)),
+    };
+    number.map(Scalar::Number)
+}
+
+/// 単項マイナス。
+pub fn negate<N: Number>(operand: &Scalar<N>) -> Result<Scalar<N>, EvalError> {
+    match operand {
+        Scalar::Number(n) => n.try_neg().map(Scalar::Number),
+        Scalar::Bool(_) => Err(mismatch(Type::Number, Type::Bool)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mod_12_5::parser::parse;
+
+    #[test]
+    fn static_types() {
+        let t = |input: &str| type_of(&parse(input).unwrap());
+        assert_eq!(t("1 + x"), Ok(Type::Number));
+        assert_eq!(t("(x < 3) == (y >= 2)"), Ok(Type::Bool));
+        assert_eq!(t("let ok = x != 0 in if ok then 1 else 2"), Ok(Type::Number));
+        assert_eq!(t("-(1 < 2)"), Err(mismatch(Type::Number, Type::Bool)));
+        assert_eq!(t("if x then 1 else 2"), Err(mismatch(Type::Bool, Type::Number)));
+        assert_eq!(
+            t("if true then 1 else false"),
+            Err(mismatch(Type::Number, Type::Bool))
+        );
+        assert_eq!(t("1 == true"), Err(mismatch(Type::Number, Type::Bool)));
+        assert_eq!(
+            t("let b = true in b * 2"),
+            Err(mismatch(Type::Number, Type::Bool))
+        );
+        assert_eq!(
+            t("(1 < 2) + 1").unwrap_err().to_string(),
+            "type mismatch: expected number, found boolean"
+        );
+    }
+
+    #[test]
+    fn scalars() {
+        let (one, yes) = (Scalar::Number(1), Scalar::<i64>::Bool(true));
+        assert_eq!(
+            apply(Operation::Lt, &one, &Scalar::Number(2)),
+            Ok(Scalar::Bool(true))
+        );
+        assert_eq!(
+            apply(Operation::Ne, &yes, &Scalar::Bool(false)),
+            Ok(Scalar::Bool(true))
+        );
+        assert_eq!(apply(Operation::Eq, &yes, &yes), Ok(Scalar::Bool(true)));
+        assert_eq!(
+            apply(Operation::Add, &one, &yes),
+            Err(mismatch(Type::Number, Type::Bool))
+        );
+        assert_eq!(negate(&yes), Err(mismatch(Type::Number, Type::Bool)));
+        assert_eq!(yes.clone().number(), Err(mismatch(Type::Number, Type::Bool)));
+        assert_eq!(yes.to_string(), "true");
+    }
+}
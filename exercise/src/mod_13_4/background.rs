@@ -0,0 +1,379 @@
+//! 記録をキューに入れてすぐに戻り、別のスレッドで中身のロガーに渡すロガー。
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+
+use crate::mod_13_4::record::{Record, Value};
+use crate::Logger;
+
+/// キューがいっぱいのときに、新しい記録をどうするか。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    /// 空きができるまで、記録したスレッドを待たせます。記録は失われません。
+    Block,
+    /// 新しい記録を捨てます。
+    DropNewest,
+    /// いちばん古い記録を捨てて、新しい記録を入れます。
+    DropOldest,
+}
+
+/// 中身のロガーが遅くても、記録したスレッドを待たせないロガー。
+///
+/// 記録は `capacity` 件までキューに入り、ワーカースレッドが入れた順に中身の
+/// ロガーへ渡します。`flush` と `Drop` は、キューが空になり、中身のロガーが
+/// すべてを受け取るまで待ちます。中身のロガーがパニックするとワーカーは止まり、
+/// それ以降の記録は捨てた数に数えます。
+pub struct AsyncLogger {
+    shared: Arc<Shared>,
+    worker: Option<JoinHandle<()>>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    capacity: usize,
+    overflow: Overflow,
+    /// キューに記録が入るか、閉じられたとき。
+    queued: Condvar,
+    /// ワーカーが記録を 1 件渡し終えるか、止まったとき。
+    delivered: Condvar,
+}
+
+struct State {
+    queue: VecDeque<Entry>,
+    /// ワーカーが中身のロガーに記録を渡している途中か。
+    busy: bool,
+    /// `Drop` が始まり、ワーカーはキューを空にしたら終わる。
+    closed: bool,
+    /// ワーカーが止まった。
+    stopped: bool,
+    /// `Overflow::Block` で空きを待っているスレッドの数。
+    waiting: usize,
+    dropped: u64,
+}
+
+/// スレッドをまたいで送れるように、借用をやめた記録。
+struct Entry {
+    target: String,
+    verbosity: u8,
+    prefix: String,
+    message: String,
+    fields: Vec<(String, Field)>,
+}
+
+enum Field {
+    Str(String),
+    /// 文字列以外の値は借用を含まないので、そのまま持ちます。
+    Other(Value<'static>),
+}
+
+impl AsyncLogger {
+    /// `inner` に渡すワーカースレッドを起動します。`capacity` は 1 以上です。
+    pub fn new<L: Logger + Send + 'static>(
+        inner: L,
+        capacity: usize,
+        overflow: Overflow,
+    ) -> AsyncLogger {
+        assert!(capacity > 0, "the queue needs room for at least one record");
+        let state = State {
+            queue: VecDeque::with_capacity(capacity),
+            busy: false,
+            closed: false,
+            stopped: false,
+            waiting: 0,
+            dropped: 0,
+        };
+        let shared = Arc::new(Shared {
+            state: Mutex::new(state),
+            capacity,
+            overflow,
+            queued: Condvar::new(),
+            delivered: Condvar::new(),
+        });
+        let worker = {
+            let shared = shared.clone();
+            thread::spawn(move || work(&shared, inner))
+        };
+        AsyncLogger { shared, worker: Some(worker) }
+    }
+
+    /// キューがあふれたか、ワーカーが止まっていたために捨てた記録の数。
+    pub fn dropped(&self) -> u64 {
+        self.shared.lock().dropped
+    }
+
+    /// それまでの記録が、すべて中身のロガーに渡るまで待ちます。
+    pub fn flush(&self) {
+        let mut state = self.shared.lock();
+        while !state.stopped && (state.busy || !state.queue.is_empty()) {
+            state = self.shared.wait(&self.shared.delivered, state);
+        }
+    }
+
+    fn push(&self, entry: Entry) {
+        let shared = &self.shared;
+        let mut state = shared.lock();
+        if state.queue.len() >= shared.capacity && !state.stopped {
+            match shared.overflow {
+                Overflow::Block => {
+                    state.waiting += 1;
+                    while state.queue.len() >= shared.capacity && !state.stopped {
+                        state = shared.wait(&shared.delivered, state);
+                    }
+                    state.waiting -= 1;
+                }
+                Overflow::DropNewest => {
+                    state.dropped += 1;
+                    return;
+                }
+                Overflow::DropOldest => {
+                    state.queue.pop_front();
+                    state.dropped += 1;
+                }
+            }
+        }
+        if state.stopped {
+            state.dropped += 1;
+            return;
+        }
+        state.queue.push_back(entry);
+        shared.queued.notify_one();
+    }
+}
+
+impl Shared {
+    /// ロックを取ります。ロックを持ったままパニックする処理はないので、毒された
+    /// ロックもそのまま使います。
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn wait<'a>(
+        &self,
+        condvar: &Condvar,
+        state: MutexGuard<'a, State>,
+    ) -> MutexGuard<'a, State> {
+        condvar.wait(state).unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Logger for AsyncLogger {
+    fn log(&self, verbosity: u8, message: &str) {
+        self.log_record(&Record {
+            target: "",
+            verbosity,
+            prefix: "",
+            message,
+            fields: &[],
+        });
+    }
+
+    fn log_record(&self, record: &Record) {
+        let fields = record
+            .fields
+            .iter()
+            .map(|&(key, value)| {
+                let field = match value {
+                    Value::Str(s) => Field::Str(s.to_owned()),
+                    Value::I64(n) => Field::Other(Value::I64(n)),
+                    Value::U64(n) => Field::Other(Value::U64(n)),
+                    Value::F64(x) => Field::Other(Value::F64(x)),
+                    Value::Bool(b) => Field::Other(Value::Bool(b)),
+                };
+                (key.to_owned(), field)
+            })
+            .collect();
+        self.push(Entry {
+            target: record.target.to_owned(),
+            verbosity: record.verbosity,
+            prefix: record.prefix.to_owned(),
+            message: record.message.to_owned(),
+            fields,
+        });
+    }
+}
+
+impl Drop for AsyncLogger {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        // 記録しているスレッドは `self` を借りているので、ここでは待っていません。
+        debug_assert_eq!(state.waiting, 0);
+        state.closed = true;
+        drop(state);
+        self.shared.queued.notify_one();
+        if let Some(worker) = self.worker.take() {
+            // 中身のロガーのパニックは、ワーカーの中で終わらせます。
+            let _ = worker.join();
+        }
+    }
+}
+
+fn work(shared: &Shared, inner: impl Logger) {
+    // パニックで抜けても、待っているスレッドを起こします。
+    let _stopped = Stopped(shared);
+    loop {
+        let entry = {
+            let mut state = shared.lock();
+            state.busy = false;
+            shared.delivered.notify_all();
+            loop {
+                if let Some(entry) = state.queue.pop_front() {
+                    // 同じロックの中で印を付け、`flush` が渡している途中の記録を
+                    // 見落とさないようにします。
+                    state.busy = true;
+                    break entry;
+                }
+                if state.closed {
+                    return;
+                }
+                state = shared.wait(&shared.queued, state);
+            }
+        };
+        let fields: Vec<(&str, Value)> = entry
+            .fields
+            .iter()
+            .map(|(key, field)| match field {
+                Field::Str(s) => (key.as_str(), Value::Str(s)),
+                Field::Other(value) => (key.as_str(), *value),
+            })
+            .collect();
+        inner.log_record(&Record {
+            target: &entry.target,
+            verbosity: entry.verbosity,
+            prefix: &entry.prefix,
+            message: &entry.message,
+            fields: &fields,
+        });
+    }
+}
+
+/// ワーカーが終わるときに、残った記録を捨てて止まったことを知らせます。
+struct Stopped<'a>(&'a Shared);
+
+impl Drop for Stopped<'_> {
+    fn drop(&mut self) {
+        let mut state = self.0.lock();
+        state.dropped += state.queue.len() as u64;
+        state.queue.clear();
+        state.busy = false;
+        state.stopped = true;
+        self.0.delivered.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// `gate` が開くまで、受け取った記録を渡さずに待つロガー。
+    #[derive(Default)]
+    struct Slow {
+        gate: Mutex<bool>,
+        opened: Condvar,
+        received: Mutex<Vec<String>>,
+    }
+
+    impl Slow {
+        fn open(&self) {
+            *self.gate.lock().unwrap() = true;
+            self.opened.notify_all();
+        }
+
+        fn received(&self) -> Vec<String> {
+            self.received.lock().unwrap().clone()
+        }
+    }
+
+    impl Logger for Slow {
+        fn log(&self, verbosity: u8, message: &str) {
+            let mut open = self.gate.lock().unwrap();
+            while !*open {
+                open = self.opened.wait(open).unwrap();
+            }
+            self.received.lock().unwrap().push(format!("{verbosity} {message}"));
+        }
+    }
+
+    /// 最初の記録でワーカーを止めたまま、さらに `count` 件を記録します。
+    fn burst(overflow: Overflow, count: usize) -> (AsyncLogger, Arc<Slow>) {
+        let slow = Arc::new(Slow::default());
+        let logger = AsyncLogger::new(slow.clone(), 2, overflow);
+        logger.log(1, "0");
+        while !logger.shared.lock().busy {
+            thread::yield_now();
+        }
+        for i in 1..=count {
+            logger.log(1, &i.to_string());
+        }
+        (logger, slow)
+    }
+
+    #[test]
+    fn drops_newest() {
+        let (logger, slow) = burst(Overflow::DropNewest, 4);
+        assert_eq!(logger.dropped(), 2);
+        slow.open();
+        logger.flush();
+        assert_eq!(slow.received(), ["1 0", "1 1", "1 2"]);
+    }
+
+    #[test]
+    fn drops_oldest() {
+        let (logger, slow) = burst(Overflow::DropOldest, 4);
+        assert_eq!(logger.dropped(), 2);
+        slow.open();
+        logger.flush();
+        assert_eq!(slow.received(), ["1 0", "1 3", "1 4"]);
+    }
+
+    #[test]
+    fn blocks_until_there_is_room() {
+        let (logger, slow) = burst(Overflow::Block, 2);
+        let logger = Arc::new(logger);
+        let blocked = {
+            let logger = logger.clone();
+            thread::spawn(move || logger.log(1, "3"))
+        };
+        while logger.shared.lock().waiting == 0 {
+            thread::yield_now();
+        }
+        assert!(!blocked.is_finished());
+        slow.open();
+        blocked.join().unwrap();
+        logger.flush();
+        assert_eq!(logger.dropped(), 0);
+        assert_eq!(slow.received(), ["1 0", "1 1", "1 2", "1 3"]);
+    }
+
+    #[test]
+    fn drop_drains_the_queue_and_keeps_fields() {
+        let (logger, slow) = burst(Overflow::Block, 1);
+        logger.log_target(
+            "http",
+            2,
+            "request",
+            &[("path", "/a b".into()), ("ms", 3i64.into())],
+        );
+        slow.open();
+        drop(logger);
+        assert_eq!(
+            slow.received(),
+            ["1 0", "1 1", "2 http: request path=\"/a b\" ms=3"]
+        );
+    }
+
+    #[test]
+    fn counts_records_after_a_panic() {
+        struct Panics;
+        impl Logger for Panics {
+            fn log(&self, _: u8, _: &str) {
+                panic!("sink failed");
+            }
+        }
+        let logger = AsyncLogger::new(Panics, 4, Overflow::Block);
+        logger.log(1, "boom");
+        logger.flush();
+        logger.log(1, "lost");
+        assert_eq!(logger.dropped(), 1);
+    }
+}
@@ -0,0 +1,201 @@
+//! ほかのロガーを包んで振る舞いを足すロガー。
+//!
+//! どれも中身の型 `L` について汎用なので、`Box<dyn Logger>` や
+//! `Arc<dyn Logger>` を含め、任意の `Logger` を組み合わせられます。
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::mod_13_4::record::Record;
+use crate::Logger;
+
+/// 同じメッセージを、登録した順にすべてのロガーへ送ります。
+pub struct Tee<L> {
+    outputs: Vec<L>,
+}
+
+impl<L: Logger> Tee<L> {
+    pub fn new(outputs: Vec<L>) -> Tee<L> {
+        Tee { outputs }
+    }
+}
+
+impl<L: Logger> Logger for Tee<L> {
+    fn log(&self, verbosity: u8, message: &str) {
+        for output in &self.outputs {
+            output.log(verbosity, message);
+        }
+    }
+
+    fn log_record(&self, record: &Record) {
+        for output in &self.outputs {
+            output.log_record(record);
+        }
+    }
+}
+
+/// メッセージの前に `[tag]` を付けます。どの部品のログかを区別するのに使います。
+///
+/// 札は記録の `prefix` の後ろに足すので、入れ子にすると外側の札が前に来ます。
+/// `Prefix::new("app", Prefix::new("db", inner))` は `[app] [db] message` と
+/// 記録し、`Timestamp` と組み合わせても同じく外側から順に並びます。
+pub struct Prefix<L> {
+    tag: String,
+    inner: L,
+}
+
+impl<L: Logger> Prefix<L> {
+    pub fn new(tag: &str, inner: L) -> Prefix<L> {
+        Prefix { tag: tag.to_owned(), inner }
+    }
+}
+
+impl<L: Logger> Logger for Prefix<L> {
+    fn log(&self, verbosity: u8, message: &str) {
+        self.log_record(&Record {
+            target: "",
+            verbosity,
+            prefix: "",
+            message,
+            fields: &[],
+        });
+    }
+
+    fn log_record(&self, record: &Record) {
+        let prefix = format!("{}[{}] ", record.prefix, self.tag);
+        self.inner.log_record(&Record { prefix: &prefix, ..*record });
+    }
+}
+
+/// メッセージの前に、記録した時刻を `2024-05-01T12:34:56.789Z` の形 (UTC) で
+/// 付けます。`Prefix` と同じく、時刻は記録の `prefix` の後ろに足します。
+pub struct Timestamp<L> {
+    inner: L,
+    now: fn() -> SystemTime,
+}
+
+impl<L: Logger> Timestamp<L> {
+    pub fn new(inner: L) -> Timestamp<L> {
+        Timestamp::with_clock(inner, SystemTime::now)
+    }
+
+    /// 現在時刻を `now` で求めます。テストで時刻を固定するのに使います。
+    pub fn with_clock(inner: L, now: fn() -> SystemTime) -> Timestamp<L> {
+        Timestamp { inner, now }
+    }
+}
+
+impl<L: Logger> Logger for Timestamp<L> {
+    fn log(&self, verbosity: u8, message: &str) {
+        self.log_record(&Record {
+            target: "",
+            verbosity,
+            prefix: "",
+            message,
+            fields: &[],
+        });
+    }
+
+    fn log_record(&self, record: &Record) {
+        let prefix = format!("{}{} ", record.prefix, format_utc((self.now)()));
+        self.inner.log_record(&Record { prefix: &prefix, ..*record });
+    }
+}
+
+/// `time` を ISO 8601 形式の UTC 時刻にします。1970 年より前の時刻は 1970 年の
+/// 始まりとして扱います。
+pub fn format_utc(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs();
+    let (year, month, day) = civil_from_days((seconds / 86_400) as i64);
+    let seconds_of_day = seconds % 86_400;
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        seconds_of_day / 3600,
+        seconds_of_day / 60 % 60,
+        seconds_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+/// 1970-01-01 からの日数を、グレゴリオ暦の年、月、日にします。
+/// (Howard Hinnant の `civil_from_days` による。)
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524
+        - day_of_era / 146_096)
+        / 365;
+    let day_of_year =
+        day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    // 3 月を 0 とした月。
+    let month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month + 2) / 5 + 1) as u32;
+    let month = if month < 10 { month + 3 } else { month - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::mod_13_4::memory::Memory;
+
+    #[test]
+    fn tee_sends_to_every_output() {
+        let (a, b) = (Arc::new(Memory::default()), Arc::new(Memory::default()));
+        let tee = Tee::new(vec![a.clone(), b.clone()]);
+        tee.log(2, "hello");
+        assert_eq!(a.take(), ["2 hello"]);
+        assert_eq!(b.take(), ["2 hello"]);
+    }
+
+    #[test]
+    fn outer_prefix_comes_first() {
+        let memory = Arc::new(Memory::default());
+        let logger = Prefix::new("app", Prefix::new("db", memory.clone()));
+        logger.log(1, "slow query");
+        logger.log_target("db::pool", 2, "waiting", &[("ms", 5i64.into())]);
+        assert_eq!(
+            memory.take(),
+            ["1 [app] [db] slow query", "2 db::pool: [app] [db] waiting ms=5"]
+        );
+    }
+
+    #[test]
+    fn fields_pass_through() {
+        fn fixed() -> SystemTime {
+            UNIX_EPOCH
+        }
+        let memory = Arc::new(Memory::default());
+        let logger = Tee::new(vec![Timestamp::with_clock(
+            Prefix::new("db", memory.clone()),
+            fixed,
+        )]);
+        logger.log_kv(2, "slow query", &[("ms", 30i64.into())]);
+        assert_eq!(
+            memory.take(),
+            ["2 1970-01-01T00:00:00.000Z [db] slow query ms=30"]
+        );
+    }
+
+    #[test]
+    fn timestamps() {
+        fn fixed() -> SystemTime {
+            UNIX_EPOCH + Duration::from_millis(1_714_566_896_789)
+        }
+        let memory = Arc::new(Memory::default());
+        let boxed: Box<dyn Logger> = Box::new(memory.clone());
+        Timestamp::with_clock(boxed, fixed).log(3, "tick");
+        assert_eq!(memory.take(), ["3 2024-05-01T12:34:56.789Z tick"]);
+
+        assert_eq!(format_utc(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+        let leap_day = UNIX_EPOCH + Duration::from_secs(951_782_400);
+        assert_eq!(format_utc(leap_day), "2000-02-29T00:00:00.000Z");
+        let end_of_year = UNIX_EPOCH + Duration::from_secs(1_735_689_599);
+        assert_eq!(format_utc(end_of_year), "2024-12-31T23:59:59.000Z");
+    }
+}
@@ -0,0 +1,310 @@
+//! ファイルに追記し、大きくなったり日付が変わったりしたら新しいファイルに
+//! 切り替えるロガー。
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::mod_13_4::gzip;
+use crate::Logger;
+
+/// ファイルを切り替える条件と、古いファイルの扱い。
+#[derive(Debug, Clone, Default)]
+pub struct Rotation {
+    /// 書き足すとこのバイト数を超えるときに切り替えます。1 行だけでこれを
+    /// 超える場合は、その行だけのファイルになります。
+    pub max_size: Option<u64>,
+    /// 日付 (UTC) が変わったら切り替えます。
+    pub daily: bool,
+    /// 残しておく古いファイルの数。新しいものから `app.log.1`、`app.log.2`、…
+    /// と番号が付き、これより古いものは消します。0 なら残しません。
+    pub backups: usize,
+    /// 古いファイルを gzip で圧縮し、`app.log.1.gz` のように保存します。
+    pub compress: bool,
+}
+
+/// `verbosity=2: message` の形の行をファイルに追記します。
+///
+/// 複数のスレッドから共有できます。切り替えと圧縮は書き込みと同じロックの
+/// 中で行うので、その間はほかのスレッドの記録が待たされます。書き込みに
+/// 失敗してもプログラムは止めず、次の記録でまたファイルを開き直します。
+/// 切り替えに失敗したときは、今のファイルに書き続けます。
+pub struct FileLogger {
+    path: PathBuf,
+    rotation: Rotation,
+    now: fn() -> SystemTime,
+    current: Mutex<Current>,
+}
+
+/// 今書いているファイル。
+struct Current {
+    file: Option<File>,
+    size: u64,
+    /// 1970-01-01 からの日数 (UTC)。
+    day: u64,
+}
+
+impl FileLogger {
+    /// `path` を追記用に開きます。ファイルがなければ作ります。
+    pub fn open(
+        path: impl AsRef<Path>,
+        rotation: Rotation,
+    ) -> io::Result<FileLogger> {
+        FileLogger::with_clock(path, rotation, SystemTime::now)
+    }
+
+    /// 現在時刻を `now` で求めます。テストで日付の変わり目を作るのに使います。
+    pub fn with_clock(
+        path: impl AsRef<Path>,
+        rotation: Rotation,
+        now: fn() -> SystemTime,
+    ) -> io::Result<FileLogger> {
+        let path = path.as_ref().to_owned();
+        let file = append(&path)?;
+        let metadata = file.metadata()?;
+        // 既存のファイルは、最後に書かれた日のものとして扱います。
+        let written = if metadata.len() > 0 { metadata.modified()? } else { now() };
+        let current =
+            Current { file: Some(file), size: metadata.len(), day: day(written) };
+        Ok(FileLogger { path, rotation, now, current: Mutex::new(current) })
+    }
+
+    fn write_line(&self, line: &str) -> io::Result<()> {
+        let mut current = self.current.lock().unwrap_or_else(|e| e.into_inner());
+        let today = day((self.now)());
+        let too_big = self.rotation.max_size.is_some_and(|max| {
+            current.size > 0 && current.size + line.len() as u64 > max
+        });
+        let new_day = self.rotation.daily && today != current.day;
+        current.day = today;
+        if too_big || new_day {
+            current.file = None;
+            // 切り替えに失敗しても、今のファイルにそのまま書き続けます。大きさは
+            // 0 から数え直すので、次の記録でまた切り替えて古いファイルを消して
+            // いくことはありません。
+            let _ = self.rotate();
+            current.size = 0;
+            current.file = Some(append(&self.path)?);
+        }
+        if current.file.is_none() {
+            let file = append(&self.path)?;
+            current.size = file.metadata()?.len();
+            current.file = Some(file);
+        }
+        let file = current.file.as_mut().expect("opened above");
+        file.write_all(line.as_bytes())?;
+        current.size += line.len() as u64;
+        Ok(())
+    }
+
+    /// 今のファイルを `path.1` にし、古いファイルの番号を 1 つずつずらします。
+    /// 今のファイルがほかから消されていれば、何もしません。
+    fn rotate(&self) -> io::Result<()> {
+        let backups = self.rotation.backups;
+        if !self.path.exists() {
+            return Ok(());
+        }
+        if backups == 0 {
+            return remove_if_exists(&self.path);
+        }
+        remove_if_exists(&self.backup(backups))?;
+        for n in (1..backups).rev() {
+            let from = self.backup(n);
+            if from.exists() {
+                fs::rename(from, self.backup(n + 1))?;
+            }
+        }
+        if self.rotation.compress {
+            let data = fs::read(&self.path)?;
+            fs::write(self.backup(1), gzip::compress(&data))?;
+            fs::remove_file(&self.path)
+        } else {
+            fs::rename(&self.path, self.backup(1))
+        }
+    }
+
+    /// `n` 番目に新しい古いファイルの名前。
+    fn backup(&self, n: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{n}"));
+        if self.rotation.compress {
+            name.push(".gz");
+        }
+        name.into()
+    }
+}
+
+impl Logger for FileLogger {
+    fn log(&self, verbosity: u8, message: &str) {
+        let _ = self.write_line(&format!("verbosity={verbosity}: {message}\n"));
+    }
+}
+
+fn day(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() / 86_400
+}
+
+fn append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+
+    /// テストごとに空のディレクトリを作ります。
+    fn directory(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir()
+            .join(format!("file-logger-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn read(path: impl AsRef<Path>) -> String {
+        fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn rotates_by_size() {
+        let dir = directory("size");
+        let path = dir.join("app.log");
+        let rotation =
+            Rotation { max_size: Some(50), backups: 2, ..Rotation::default() };
+        let logger = FileLogger::open(&path, rotation).unwrap();
+        // 1 行は 23 バイトなので、2 行ごとに切り替わります。
+        for i in 0..7 {
+            logger.log(1, &format!("message {i}"));
+        }
+        assert_eq!(read(&path), "verbosity=1: message 6\n");
+        assert_eq!(
+            read(dir.join("app.log.1")),
+            "verbosity=1: message 4\nverbosity=1: message 5\n"
+        );
+        assert_eq!(
+            read(dir.join("app.log.2")),
+            "verbosity=1: message 2\nverbosity=1: message 3\n"
+        );
+        assert!(!dir.join("app.log.3").exists());
+
+        // 開き直しても、すでに書いた分を数えます。
+        drop(logger);
+        let rotation =
+            Rotation { max_size: Some(50), backups: 2, ..Rotation::default() };
+        let logger = FileLogger::open(&path, rotation).unwrap();
+        logger.log(1, "message 7");
+        logger.log(1, "message 8");
+        assert_eq!(read(&path), "verbosity=1: message 8\n");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn live_file_removed_between_writes() {
+        let dir = directory("removed");
+        let path = dir.join("app.log");
+        let rotation =
+            Rotation { max_size: Some(50), backups: 2, ..Rotation::default() };
+        let logger = FileLogger::open(&path, rotation).unwrap();
+        for i in 0..4 {
+            logger.log(1, &format!("message {i}"));
+        }
+        fs::remove_file(&path).unwrap();
+        // 切り替える大きさを超えていても、消えたファイルは切り替えずに作り
+        // 直し、古いファイルはずらしません。
+        for i in 4..6 {
+            logger.log(1, &format!("message {i}"));
+        }
+        assert_eq!(read(&path), "verbosity=1: message 4\nverbosity=1: message 5\n");
+        assert_eq!(
+            read(dir.join("app.log.1")),
+            "verbosity=1: message 0\nverbosity=1: message 1\n"
+        );
+        assert!(!dir.join("app.log.2").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    static NOW: AtomicU64 = AtomicU64::new(0);
+
+    fn clock() -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(NOW.load(Ordering::SeqCst))
+    }
+
+    #[test]
+    fn rotates_daily_and_compresses() {
+        let dir = directory("daily");
+        let path = dir.join("app.log");
+        NOW.store(86_400 * 20_000 + 3600 * 23, Ordering::SeqCst);
+        let rotation = Rotation {
+            daily: true,
+            backups: 1,
+            compress: true,
+            ..Rotation::default()
+        };
+        let logger = FileLogger::with_clock(&path, rotation, clock).unwrap();
+        logger.log(2, "before midnight");
+        NOW.fetch_add(1800, Ordering::SeqCst);
+        logger.log(2, "still the same day");
+        NOW.fetch_add(3600, Ordering::SeqCst);
+        logger.log(2, "next day");
+        assert_eq!(read(&path), "verbosity=2: next day\n");
+        let gz = fs::read(dir.join("app.log.1.gz")).unwrap();
+        assert_eq!(
+            String::from_utf8(gzip::decompress(&gz)).unwrap(),
+            "verbosity=2: before midnight\nverbosity=2: still the same day\n"
+        );
+
+        NOW.fetch_add(86_400, Ordering::SeqCst);
+        logger.log(2, "two days later");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn shared_across_threads() {
+        let dir = directory("threads");
+        let path = dir.join("app.log");
+        let rotation =
+            Rotation { max_size: Some(1000), backups: 100, ..Rotation::default() };
+        let logger = Arc::new(FileLogger::open(&path, rotation).unwrap());
+        let handles: Vec<_> = (0..4)
+            .map(|t| {
+                let logger = logger.clone();
+                thread::spawn(move || {
+                    for i in 0..100 {
+                        logger.log(3, &format!("thread {t} line {i:02}"));
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        let mut lines = Vec::new();
+        for entry in fs::read_dir(&dir).unwrap() {
+            let text = read(entry.unwrap().path());
+            assert!(text.len() <= 1000);
+            lines.extend(text.lines().map(str::to_owned));
+        }
+        lines.sort();
+        assert_eq!(lines.len(), 400);
+        assert_eq!(lines[0], "verbosity=3: thread 0 line 00");
+        assert!(lines
+            .iter()
+            .all(|l| l.len() == "verbosity=3: thread 0 line 00".len()));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
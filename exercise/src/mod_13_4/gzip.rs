@@ -0,0 +1,284 @@
+//! 古いログファイルを小さくするための gzip 圧縮。
+//!
+//! 外部のクレートに頼らないように、LZ77 と固定ハフマン符号だけを使う簡単な
+//! DEFLATE を実装しています。圧縮率は `gzip` コマンドに及びませんが、同じ行が
+//! 繰り返しがちなログには十分に効き、出力は `gzip -d` などでそのまま展開できます。
+//!
+//! `flate2` を使えばずっと短く書けますが、このパッケージの依存は全部の
+//! バイナリで共有され、今は `thiserror` と `anyhow` だけです。ローテーションの
+//! 圧縮という 1 つの任意の機能のために、`flate2` とそれが引き込むクレートを
+//! すべての演習に加えるより、圧縮するだけの小さな実装を持つほうを選んでいます。
+//! 展開は必要ないので、確認用の展開器はテストの中にだけ置きます。
+
+/// `data` を gzip 形式 (RFC 1952) で圧縮します。
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    // 圧縮方式 8 (DEFLATE)、フラグなし、時刻なし、OS 不明。
+    let mut out = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff];
+    let mut bits = BitWriter { out, buffer: 0, count: 0 };
+    deflate(data, &mut bits);
+    out = bits.finish();
+    out.extend_from_slice(&crc32(data).to_le_bytes());
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out
+}
+
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const WINDOW: usize = 32 * 1024;
+/// 一致を探すときにたどる候補の数の上限。
+const MAX_CHAIN: usize = 64;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83,
+    99, 115, 131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5,
+    5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769,
+    1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11,
+    11, 12, 12, 13, 13,
+];
+
+/// 全体を、固定ハフマン符号を使う 1 つのブロックにします。
+fn deflate(data: &[u8], bits: &mut BitWriter) {
+    // 最後のブロック、固定ハフマン符号。
+    bits.write(1, 1);
+    bits.write(1, 2);
+    let mut matcher = Matcher {
+        data,
+        head: vec![usize::MAX; HASH_SIZE],
+        previous: vec![usize::MAX; data.len()],
+    };
+    let mut i = 0;
+    while i < data.len() {
+        let (length, distance) = matcher.longest_match(i);
+        if length >= MIN_MATCH {
+            write_match(bits, length, distance);
+            for j in i..i + length {
+                matcher.insert(j);
+            }
+            i += length;
+        } else {
+            write_literal(bits, data[i].into());
+            matcher.insert(i);
+            i += 1;
+        }
+    }
+    write_literal(bits, 256);
+}
+
+const HASH_SIZE: usize = 1 << 15;
+
+/// これまでに読んだ位置を、先頭 3 バイトのハッシュで引けるようにしたもの。
+struct Matcher<'a> {
+    data: &'a [u8],
+    /// ハッシュごとの、最後に現れた位置。
+    head: Vec<usize>,
+    /// 各位置の、同じハッシュで 1 つ前に現れた位置。
+    previous: Vec<usize>,
+}
+
+impl Matcher<'_> {
+    fn hash(&self, i: usize) -> usize {
+        let d = self.data;
+        ((d[i] as usize) << 10 ^ (d[i + 1] as usize) << 5 ^ d[i + 2] as usize)
+            % HASH_SIZE
+    }
+
+    fn insert(&mut self, i: usize) {
+        if i + MIN_MATCH <= self.data.len() {
+            let h = self.hash(i);
+            self.previous[i] = self.head[h];
+            self.head[h] = i;
+        }
+    }
+
+    /// `at` から始まる、窓の中で最も長い一致の長さと距離。
+    fn longest_match(&self, at: usize) -> (usize, usize) {
+        if at + MIN_MATCH > self.data.len() {
+            return (0, 0);
+        }
+        let limit = (self.data.len() - at).min(MAX_MATCH);
+        let (mut best_length, mut best_distance) = (0, 0);
+        let mut candidate = self.head[self.hash(at)];
+        for _ in 0..MAX_CHAIN {
+            if candidate == usize::MAX || at - candidate > WINDOW {
+                break;
+            }
+            let length = self.data[candidate..]
+                .iter()
+                .zip(&self.data[at..at + limit])
+                .take_while(|(a, b)| a == b)
+                .count();
+            if length > best_length {
+                (best_length, best_distance) = (length, at - candidate);
+                if length == limit {
+                    break;
+                }
+            }
+            candidate = self.previous[candidate];
+        }
+        (best_length, best_distance)
+    }
+}
+
+/// 固定ハフマン符号で、リテラルか長さの符号 `symbol` を書きます。
+fn write_literal(bits: &mut BitWriter, symbol: u16) {
+    let (code, length) = match symbol {
+        0..=143 => (0x30 + symbol, 8),
+        144..=255 => (0x190 + symbol - 144, 9),
+        256..=279 => (symbol - 256, 7),
+        _ => (0xc0 + symbol - 280, 8),
+    };
+    bits.write_code(code.into(), length);
+}
+
+fn write_match(bits: &mut BitWriter, length: usize, distance: usize) {
+    let index = LENGTH_BASE.iter().rposition(|&b| b as usize <= length).unwrap();
+    write_literal(bits, 257 + index as u16);
+    bits.write((length - LENGTH_BASE[index] as usize) as u32, LENGTH_EXTRA[index]);
+    let index = DISTANCE_BASE.iter().rposition(|&b| b as usize <= distance).unwrap();
+    bits.write_code(index as u32, 5);
+    bits.write(
+        (distance - DISTANCE_BASE[index] as usize) as u32,
+        DISTANCE_EXTRA[index],
+    );
+}
+
+/// 下位のビットから詰めていくビット列。
+struct BitWriter {
+    out: Vec<u8>,
+    buffer: u32,
+    count: u8,
+}
+
+impl BitWriter {
+    /// `value` の下位 `count` ビットを、下位のビットから書きます。
+    fn write(&mut self, value: u32, count: u8) {
+        self.buffer |= value << self.count;
+        self.count += count;
+        while self.count >= 8 {
+            self.out.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// ハフマン符号は上位のビットから書きます。
+    fn write_code(&mut self, code: u32, length: u8) {
+        self.write(code.reverse_bits() >> (32 - length), length);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.out.push(self.buffer as u8);
+        }
+        self.out
+    }
+}
+
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 == 1 { 0xedb8_8320 ^ (c >> 1) } else { c >> 1 };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+};
+
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0, |crc, &b| {
+        CRC_TABLE[((crc ^ b as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+/// 固定ハフマン符号のブロックだけを読める、確認用の展開器。
+#[cfg(test)]
+pub fn decompress(gz: &[u8]) -> Vec<u8> {
+    assert_eq!(gz[..4], [0x1f, 0x8b, 8, 0]);
+    let mut position = 10 * 8;
+    let mut bit = || {
+        let b = gz[position / 8] >> (position % 8) & 1;
+        position += 1;
+        u32::from(b)
+    };
+    fn read(count: u8, bit: &mut dyn FnMut() -> u32) -> u32 {
+        (0..count).fold(0, |v, i| v | bit() << i)
+    }
+    assert_eq!(read(3, &mut bit), 0b011, "a final block with fixed codes");
+    let mut out: Vec<u8> = Vec::new();
+    loop {
+        let mut code = 0;
+        let mut length = 0;
+        let symbol = loop {
+            code = code << 1 | bit();
+            length += 1;
+            match (length, code) {
+                (7, 0..=0x17) => break code + 256,
+                (8, 0x30..=0xbf) => break code - 0x30,
+                (8, 0xc0..=0xc7) => break code - 0xc0 + 280,
+                (9, 0x190..=0x1ff) => break code - 0x190 + 144,
+                _ => {}
+            }
+        } as usize;
+        match symbol {
+            0..=255 => out.push(symbol as u8),
+            256 => break,
+            _ => {
+                let i = symbol - 257;
+                let length = LENGTH_BASE[i] as usize
+                    + read(LENGTH_EXTRA[i], &mut bit) as usize;
+                let i = (0..5).fold(0, |v, _| v << 1 | bit()) as usize;
+                let distance = DISTANCE_BASE[i] as usize
+                    + read(DISTANCE_EXTRA[i], &mut bit) as usize;
+                for _ in 0..length {
+                    out.push(out[out.len() - distance]);
+                }
+            }
+        }
+    }
+    let trailer = &gz[gz.len() - 8..];
+    assert_eq!(trailer[..4], crc32(&out).to_le_bytes());
+    assert_eq!(trailer[4..], (out.len() as u32).to_le_bytes());
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let log: String = (0..500)
+            .map(|i| {
+                format!("verbosity=2: request {} done in {}ms\n", i % 7, i % 13)
+            })
+            .collect();
+        let long_runs =
+            [vec![b'a'; 1000], (0..=255).collect(), vec![0; 70_000]].concat();
+        for data in [&b""[..], b"a", b"abcabcabcabc", log.as_bytes(), &long_runs] {
+            let gz = compress(data);
+            assert_eq!(decompress(&gz), data);
+        }
+        assert!(compress(log.as_bytes()).len() * 5 < log.len());
+    }
+
+    #[test]
+    fn crc() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+}
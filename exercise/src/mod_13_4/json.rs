@@ -0,0 +1,174 @@
+//! 1 件のログを 1 行の JSON オブジェクトとして書き出すロガー (JSON Lines)。
+
+use std::fmt::Write as _;
+use std::io::Write;
+use std::sync::Mutex;
+
+use crate::mod_13_4::record::{Record, Value};
+use crate::Logger;
+
+/// `{"target":"net","verbosity":2,"message":"...","fields":{"key":value,...}}`
+/// の形の行を `out` に書きます。包むロガーが付けた札や時刻は `message` の前に
+/// 付けます。部品の指定がなければ `target` を、付加情報が
+/// なければ `fields` を省きます。付加情報は `fields` の中に記録の順に並ぶので、
+/// `message` のようなキーを使っても上の項目とは重なりません。
+///
+/// 書き込みの失敗は無視します。ログが書けないことでプログラムを止めない
+/// ためです。
+pub struct JsonLinesLogger<W> {
+    out: Mutex<W>,
+}
+
+impl<W: Write> JsonLinesLogger<W> {
+    pub fn new(out: W) -> JsonLinesLogger<W> {
+        JsonLinesLogger { out: Mutex::new(out) }
+    }
+}
+
+impl<W: Write> Logger for JsonLinesLogger<W> {
+    fn log(&self, verbosity: u8, message: &str) {
+        self.log_record(&Record {
+            target: "",
+            verbosity,
+            prefix: "",
+            message,
+            fields: &[],
+        });
+    }
+
+    fn log_record(&self, record: &Record) {
+        let mut line = String::from("{");
+        if !record.target.is_empty() {
+            line.push_str("\"target\":");
+            push_string(&mut line, record.target);
+            line.push(',');
+        }
+        write!(line, "\"verbosity\":{},\"message\":", record.verbosity).unwrap();
+        push_string(&mut line, &format!("{}{}", record.prefix, record.message));
+        if !record.fields.is_empty() {
+            line.push_str(",\"fields\":{");
+            for (i, (key, value)) in record.fields.iter().enumerate() {
+                if i > 0 {
+                    line.push(',');
+                }
+                push_string(&mut line, key);
+                line.push(':');
+                push_value(&mut line, value);
+            }
+            line.push('}');
+        }
+        line.push_str("}\n");
+        // 1 行を 1 回で書くので、複数のスレッドから書いても行は混ざりません。
+        let mut out = self.out.lock().unwrap_or_else(|e| e.into_inner());
+        let _ = out.write_all(line.as_bytes());
+    }
+}
+
+fn push_value(line: &mut String, value: &Value) {
+    match *value {
+        Value::Str(s) => push_string(line, s),
+        Value::I64(n) => write!(line, "{n}").unwrap(),
+        Value::U64(n) => write!(line, "{n}").unwrap(),
+        // JSON には NaN や無限大がないので `null` にします。
+        Value::F64(x) if !x.is_finite() => line.push_str("null"),
+        Value::F64(x) => write!(line, "{x}").unwrap(),
+        Value::Bool(b) => write!(line, "{b}").unwrap(),
+    }
+}
+
+/// `s` を引用符で囲み、JSON の規則どおりにエスケープして `line` に足します。
+fn push_string(line: &mut String, s: &str) {
+    line.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => line.push_str("\\\""),
+            '\\' => line.push_str("\\\\"),
+            '\n' => line.push_str("\\n"),
+            '\r' => line.push_str("\\r"),
+            '\t' => line.push_str("\\t"),
+            '\u{8}' => line.push_str("\\b"),
+            '\u{c}' => line.push_str("\\f"),
+            c if c < ' ' => write!(line, "\\u{:04x}", c as u32).unwrap(),
+            c => line.push(c),
+        }
+    }
+    line.push('"');
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn lines(log: impl Fn(&JsonLinesLogger<Vec<u8>>)) -> String {
+        let logger = JsonLinesLogger::new(Vec::new());
+        log(&logger);
+        String::from_utf8(logger.out.into_inner().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn one_object_per_line() {
+        let output = lines(|logger| {
+            logger.log(3, "plain");
+            logger.log_target(
+                "http::server",
+                1,
+                "request done",
+                &[
+                    ("status", 200i64.into()),
+                    ("bytes", 512u64.into()),
+                    ("ms", 1.5.into()),
+                    ("ratio", f64::NAN.into()),
+                    ("cached", true.into()),
+                ],
+            );
+        });
+        assert_eq!(
+            output,
+            concat!(
+                "{\"verbosity\":3,\"message\":\"plain\"}\n",
+                "{\"target\":\"http::server\",\"verbosity\":1,",
+                "\"message\":\"request done\",\"fields\":{\"status\":200,",
+                "\"bytes\":512,\"ms\":1.5,\"ratio\":null,\"cached\":true}}\n",
+            )
+        );
+    }
+
+    #[test]
+    fn escapes_strings() {
+        let output = lines(|logger| {
+            logger.log_kv(
+                2,
+                "say \"hi\"\nC:\\tmp\t\u{1}\u{8}\u{c}\r",
+                &[("名前\u{7f}", "é😀\u{1f}".into())],
+            );
+        });
+        assert_eq!(
+            output,
+            concat!(
+                r#"{"verbosity":2,"message":"say \"hi\"\nC:\\tmp\t\u0001\b\f\r","#,
+                "\"fields\":{\"名前\u{7f}\":\"é😀\\u001f\"}}\n",
+            )
+        );
+        assert_eq!(output.lines().count(), 1);
+    }
+
+    #[test]
+    fn fields_cannot_shadow_the_record() {
+        let output = lines(|logger| {
+            logger.log_target(
+                "app",
+                1,
+                "real",
+                &[("message", "fake".into()), ("verbosity", 9i64.into())],
+            );
+        });
+        assert_eq!(
+            output,
+            concat!(
+                r#"{"target":"app","verbosity":1,"message":"real","#,
+                r#""fields":{"message":"fake","verbosity":9}}"#,
+                "\n",
+            )
+        );
+    }
+}
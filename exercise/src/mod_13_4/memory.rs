@@ -0,0 +1,23 @@
+//! テストで使う、受け取った記録を覚えておくロガー。
+
+use std::sync::Mutex;
+
+use crate::Logger;
+
+/// 受け取った記録を `"2 message"` の形で、受け取った順に覚えます。付加情報の
+/// ある記録は、`Logger::log_record` の既定の形でメッセージに入ります。
+#[derive(Default)]
+pub struct Memory(Mutex<Vec<String>>);
+
+impl Logger for Memory {
+    fn log(&self, verbosity: u8, message: &str) {
+        self.0.lock().unwrap().push(format!("{verbosity} {message}"));
+    }
+}
+
+impl Memory {
+    /// 覚えている記録を取り出し、空にします。
+    pub fn take(&self) -> Vec<String> {
+        std::mem::take(&mut self.0.lock().unwrap())
+    }
+}
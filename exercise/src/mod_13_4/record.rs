@@ -0,0 +1,129 @@
+//! メッセージに `key=value` の付加情報を付けた、構造化されたログの記録。
+
+use std::fmt;
+
+/// 付加情報の値。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value<'a> {
+    Str(&'a str),
+    I64(i64),
+    U64(u64),
+    F64(f64),
+    Bool(bool),
+}
+
+impl<'a> From<&'a str> for Value<'a> {
+    fn from(s: &'a str) -> Value<'a> {
+        Value::Str(s)
+    }
+}
+
+impl<'a> From<&'a String> for Value<'a> {
+    fn from(s: &'a String) -> Value<'a> {
+        Value::Str(s)
+    }
+}
+
+impl From<i64> for Value<'_> {
+    fn from(n: i64) -> Self {
+        Value::I64(n)
+    }
+}
+
+impl From<u64> for Value<'_> {
+    fn from(n: u64) -> Self {
+        Value::U64(n)
+    }
+}
+
+impl From<f64> for Value<'_> {
+    fn from(x: f64) -> Self {
+        Value::F64(x)
+    }
+}
+
+impl From<bool> for Value<'_> {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+/// 空白や `=`、`"` を含む文字列と空の文字列は、区切りと見分けられるように
+/// 引用符で囲んで表示します。
+impl fmt::Display for Value<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::Str(s)
+                if s.is_empty()
+                    || s.chars().any(|c| {
+                        c.is_whitespace() || c.is_control() || c == '=' || c == '"'
+                    }) =>
+            {
+                write!(f, "{s:?}")
+            }
+            Value::Str(s) => f.write_str(s),
+            Value::I64(n) => write!(f, "{n}"),
+            Value::U64(n) => write!(f, "{n}"),
+            Value::F64(x) => write!(f, "{x}"),
+            Value::Bool(b) => write!(f, "{b}"),
+        }
+    }
+}
+
+/// 1 件のログ。付加情報は渡された順に並びます。
+#[derive(Debug, Clone, Copy)]
+pub struct Record<'a> {
+    /// 記録した部品の名前。`db::pool` のようにモジュールのパスで書きます。
+    /// 空なら部品を指定していません。
+    pub target: &'a str,
+    pub verbosity: u8,
+    /// ほかのロガーを包むロガーが付けた札や時刻。外側のロガーが付けたものから
+    /// 順に並び、それぞれの後ろに空白が 1 つ付きます。メッセージの前に表示
+    /// します。
+    pub prefix: &'a str,
+    pub message: &'a str,
+    pub fields: &'a [(&'a str, Value<'a>)],
+}
+
+/// `target: prefix message key=value ...` の形で表示します。部品の指定が
+/// なければ `target: ` は付けません。
+impl fmt::Display for Record<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.target.is_empty() {
+            write!(f, "{}: ", self.target)?;
+        }
+        f.write_str(self.prefix)?;
+        f.write_str(self.message)?;
+        for (key, value) in self.fields {
+            write!(f, " {key}={value}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn display() {
+        let record = Record {
+            target: "http",
+            verbosity: 2,
+            prefix: "[web] ",
+            message: "request done",
+            fields: &[
+                ("path", "/users".into()),
+                ("agent", "curl 8.0".into()),
+                ("note", "".into()),
+                ("status", 200i64.into()),
+                ("ms", 12.5.into()),
+                ("cached", false.into()),
+            ],
+        };
+        assert_eq!(
+            record.to_string(),
+            r#"http: [web] request done path=/users agent="curl 8.0" note="" status=200 ms=12.5 cached=false"#
+        );
+    }
+}
@@ -0,0 +1,221 @@
+//! 部品 (target) ごとに詳細度を絞るフィルター。
+//!
+//! 設定は `"3,net=5,db::pool=1"` のような文字列で書きます。カンマで区切った
+//! それぞれが、部品の名前と詳細度の組か、どの組にも当てはまらない記録に使う
+//! 詳細度だけです。部品の名前はモジュールのパスとして前から比べ、`db` は `db`
+//! と `db::pool` に当てはまりますが、`dbx` には当てはまりません。いくつかの組が
+//! 当てはまるときは、最も長い名前の組を使います。
+
+use std::str::FromStr;
+
+use thiserror::Error;
+
+use crate::mod_13_4::record::Record;
+use crate::Logger;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum SpecError {
+    #[error("empty directive at position {pos}")]
+    EmptyDirective { pos: usize },
+    #[error("invalid target \"{target}\" at position {pos}")]
+    InvalidTarget { target: String, pos: usize },
+    #[error("invalid verbosity \"{text}\" at position {pos}: expected 0 to 255")]
+    InvalidVerbosity { text: String, pos: usize },
+    #[error("verbosity for \"{target}\" is given twice")]
+    DuplicateTarget { target: String },
+    #[error("default verbosity is given twice")]
+    DuplicateDefault,
+}
+
+/// 解析済みの設定。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Spec {
+    /// どの組にも当てはまらない記録の詳細度。`None` ならすべて捨てます。
+    default: Option<u8>,
+    /// 部品の名前と詳細度の組。名前の長い順に並べます。
+    targets: Vec<(String, u8)>,
+}
+
+impl Spec {
+    /// `target` の記録を通す最大の詳細度。通さないなら `None` です。
+    pub fn max_verbosity(&self, target: &str) -> Option<u8> {
+        self.targets
+            .iter()
+            .find(|(name, _)| within(target, name))
+            .map(|&(_, verbosity)| verbosity)
+            .or(self.default)
+    }
+}
+
+/// `target` が `module` そのものか、その下のモジュールか。
+fn within(target: &str, module: &str) -> bool {
+    target
+        .strip_prefix(module)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+}
+
+impl FromStr for Spec {
+    type Err = SpecError;
+
+    /// エラーの位置は、`spec` の先頭を 0 とするバイト単位の位置です。
+    fn from_str(spec: &str) -> Result<Spec, SpecError> {
+        let mut default = None;
+        let mut targets: Vec<(String, u8)> = Vec::new();
+        let mut start = 0;
+        for directive in spec.split(',') {
+            let pos = start + (directive.len() - directive.trim_start().len());
+            start += directive.len() + 1;
+            let directive = directive.trim();
+            if directive.is_empty() {
+                return Err(SpecError::EmptyDirective { pos });
+            }
+            let (target, text, text_pos) = match directive.split_once('=') {
+                Some((target, text)) => {
+                    let offset = directive.len() - text.len();
+                    let text_pos =
+                        pos + offset + (text.len() - text.trim_start().len());
+                    (Some(target.trim_end()), text.trim(), text_pos)
+                }
+                None => (None, directive, pos),
+            };
+            let verbosity = text.parse().map_err(|_| {
+                SpecError::InvalidVerbosity { text: text.to_owned(), pos: text_pos }
+            })?;
+            match target {
+                None if default.is_some() => {
+                    return Err(SpecError::DuplicateDefault)
+                }
+                None => default = Some(verbosity),
+                Some(target) if !is_module_path(target) => {
+                    return Err(SpecError::InvalidTarget {
+                        target: target.to_owned(),
+                        pos,
+                    })
+                }
+                Some(target) if targets.iter().any(|(name, _)| name == target) => {
+                    return Err(SpecError::DuplicateTarget {
+                        target: target.to_owned(),
+                    })
+                }
+                Some(target) => targets.push((target.to_owned(), verbosity)),
+            }
+        }
+        targets.sort_by_key(|(name, _)| std::cmp::Reverse(name.len()));
+        Ok(Spec { default, targets })
+    }
+}
+
+/// `db::pool` のように、識別子を `::` でつないだものか。
+fn is_module_path(target: &str) -> bool {
+    target.split("::").all(|segment| {
+        !segment.is_empty()
+            && segment.chars().all(|c| c.is_alphanumeric() || c == '_')
+    })
+}
+
+/// 記録の部品に応じた詳細度以下のメッセージだけを `inner` に渡します。部品を
+/// 指定しない記録は、既定の詳細度で絞ります。
+pub struct TargetFilter<L> {
+    spec: Spec,
+    inner: L,
+}
+
+impl<L: Logger> TargetFilter<L> {
+    pub fn new(spec: Spec, inner: L) -> TargetFilter<L> {
+        TargetFilter { spec, inner }
+    }
+}
+
+impl<L: Logger> Logger for TargetFilter<L> {
+    fn log(&self, verbosity: u8, message: &str) {
+        if self.spec.max_verbosity("").is_some_and(|max| verbosity <= max) {
+            self.inner.log(verbosity, message);
+        }
+    }
+
+    fn log_record(&self, record: &Record) {
+        let max = self.spec.max_verbosity(record.target);
+        if max.is_some_and(|max| record.verbosity <= max) {
+            self.inner.log_record(record);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::mod_13_4::memory::Memory;
+
+    #[test]
+    fn longest_prefix_wins() {
+        let spec: Spec = "3, net=5 ,db::pool=1,db=4".parse().unwrap();
+        for (target, expected) in [
+            ("", Some(3)),
+            ("app", Some(3)),
+            ("net", Some(5)),
+            ("net::tcp", Some(5)),
+            ("network", Some(3)),
+            ("db", Some(4)),
+            ("db::query", Some(4)),
+            ("db::pool", Some(1)),
+            ("db::pool::conn", Some(1)),
+            ("db::poolside", Some(4)),
+        ] {
+            assert_eq!(spec.max_verbosity(target), expected, "{target}");
+        }
+        let only_net: Spec = "net=2".parse().unwrap();
+        assert_eq!(only_net.max_verbosity("net::tcp"), Some(2));
+        assert_eq!(only_net.max_verbosity("db"), None);
+    }
+
+    #[test]
+    fn errors() {
+        for (spec, expected) in [
+            ("", SpecError::EmptyDirective { pos: 0 }),
+            ("3,,net=5", SpecError::EmptyDirective { pos: 2 }),
+            ("3, ", SpecError::EmptyDirective { pos: 3 }),
+            (
+                "3,net=loud",
+                SpecError::InvalidVerbosity { text: String::from("loud"), pos: 6 },
+            ),
+            (
+                "net = 256",
+                SpecError::InvalidVerbosity { text: String::from("256"), pos: 6 },
+            ),
+            ("=2", SpecError::InvalidTarget { target: String::new(), pos: 0 }),
+            (
+                "1, db:pool=2",
+                SpecError::InvalidTarget { target: String::from("db:pool"), pos: 3 },
+            ),
+            (
+                "db::=2",
+                SpecError::InvalidTarget { target: String::from("db::"), pos: 0 },
+            ),
+            (
+                "net=1,net=2",
+                SpecError::DuplicateTarget { target: String::from("net") },
+            ),
+            ("1,net=2,3", SpecError::DuplicateDefault),
+        ] {
+            assert_eq!(spec.parse::<Spec>(), Err(expected), "{spec:?}");
+        }
+        assert_eq!(
+            "3,net=loud".parse::<Spec>().unwrap_err().to_string(),
+            "invalid verbosity \"loud\" at position 6: expected 0 to 255"
+        );
+    }
+
+    #[test]
+    fn filters_by_target() {
+        let memory = Arc::new(Memory::default());
+        let filter = TargetFilter::new("1,net=3".parse().unwrap(), memory.clone());
+        filter.log(1, "plain");
+        filter.log(2, "plain and verbose");
+        filter.log_target("net::tcp", 3, "retrying", &[]);
+        filter.log_target("net::tcp", 4, "too verbose", &[]);
+        filter.log_target("db", 2, "too verbose", &[]);
+        assert_eq!(memory.take(), ["1 plain", "3 net::tcp: retrying"]);
+    }
+}
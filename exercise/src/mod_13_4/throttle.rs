@@ -0,0 +1,317 @@
+//! エラーが続けざまに起きたときに、ログがあふれないようにするロガー。
+
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::mod_13_4::record::Record;
+use crate::Logger;
+
+/// 同じメッセージが続いている間も、この間隔ごとに繰り返しの数を記録します。
+const REPEAT_REPORT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// トークンバケットの設定。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rate {
+    /// 1 秒あたりに補充するトークンの数。0 以上です。
+    pub per_second: f64,
+    /// ためておけるトークンの数。この数までは続けて記録できます。1 以上です。
+    pub burst: f64,
+}
+
+/// 同じ記録の繰り返しを 1 行にまとめ、詳細度ごとに記録の速さを制限します。
+///
+/// 直前と同じ詳細度、部品、メッセージ、付加情報の記録は渡さずに数え、違う記録が
+/// 来たときや `REPEAT_REPORT_INTERVAL` ごと、破棄のときに
+/// `last message repeated 57 times` と記録します。繰り返しでない記録は、詳細度
+/// ごとのトークンバケットからトークンを 1 つ使います。トークンがなければ捨てて
+/// 数え、次に記録できたときや破棄のときに `rate limit dropped 12 messages` と
+/// 記録します。まとめの行は制限を受けません。
+pub struct Throttle<L: Logger> {
+    inner: L,
+    rate: Rate,
+    now: Box<dyn Fn() -> Instant + Send + Sync>,
+    state: Mutex<State>,
+}
+
+struct State {
+    /// 直前に渡した記録。
+    last: Option<Last>,
+    buckets: HashMap<u8, Bucket>,
+}
+
+struct Last {
+    verbosity: u8,
+    target: String,
+    /// 付加情報まで含めて比べるための、表示した形。
+    text: String,
+    repeated: u64,
+    /// 最初に渡したか、最後に繰り返しの数を記録した時刻。
+    reported: Instant,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    dropped: u64,
+}
+
+impl<L: Logger> Throttle<L> {
+    /// `rate` で記録の速さを制限して `inner` に渡します。`rate` の値が範囲の
+    /// 外なら panic します。
+    pub fn new(inner: L, rate: Rate) -> Throttle<L> {
+        Throttle::with_clock(inner, rate, Instant::now)
+    }
+
+    /// 現在時刻を `now` で求めます。テストで時間の経過を作るのに使います。
+    pub fn with_clock(
+        inner: L,
+        rate: Rate,
+        now: impl Fn() -> Instant + Send + Sync + 'static,
+    ) -> Throttle<L> {
+        // `burst` が 1 より小さいと、繰り返しでない記録はすべて捨てられます。
+        assert!(rate.burst >= 1.0, "burst must be at least 1, got {}", rate.burst);
+        assert!(
+            rate.per_second >= 0.0,
+            "per_second must not be negative, got {}",
+            rate.per_second
+        );
+        let state = State { last: None, buckets: HashMap::new() };
+        Throttle { inner, rate, now: Box::new(now), state: Mutex::new(state) }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn report_repeats(&self, last: &Last) {
+        if last.repeated > 0 {
+            let message = format!("last message repeated {} times", last.repeated);
+            self.inner.log_record(&Record {
+                target: &last.target,
+                verbosity: last.verbosity,
+                prefix: "",
+                message: &message,
+                fields: &[],
+            });
+        }
+    }
+
+    fn report_drops(&self, verbosity: u8, bucket: &mut Bucket) {
+        if bucket.dropped > 0 {
+            let message = format!("rate limit dropped {} messages", bucket.dropped);
+            self.inner.log(verbosity, &message);
+            bucket.dropped = 0;
+        }
+    }
+}
+
+impl<L: Logger> Logger for Throttle<L> {
+    fn log(&self, verbosity: u8, message: &str) {
+        self.log_record(&Record {
+            target: "",
+            verbosity,
+            prefix: "",
+            message,
+            fields: &[],
+        });
+    }
+
+    fn log_record(&self, record: &Record) {
+        // まとめの行と記録の順序が入れ替わらないように、渡し終えるまでロックを
+        // 持ちます。
+        let mut state = self.lock();
+        let state = &mut *state;
+        let now = (self.now)();
+        let text = record.to_string();
+        match &mut state.last {
+            Some(last)
+                if last.verbosity == record.verbosity && last.text == text =>
+            {
+                last.repeated += 1;
+                if now.saturating_duration_since(last.reported)
+                    >= REPEAT_REPORT_INTERVAL
+                {
+                    self.report_repeats(last);
+                    (last.repeated, last.reported) = (0, now);
+                }
+                return;
+            }
+            Some(last) => self.report_repeats(last),
+            None => {}
+        }
+        state.last = None;
+
+        let rate = self.rate;
+        let bucket = state.buckets.entry(record.verbosity).or_insert(Bucket {
+            tokens: rate.burst,
+            updated: now,
+            dropped: 0,
+        });
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate.per_second).min(rate.burst);
+        bucket.updated = now;
+        if bucket.tokens < 1.0 {
+            bucket.dropped += 1;
+            return;
+        }
+        bucket.tokens -= 1.0;
+        self.report_drops(record.verbosity, bucket);
+        self.inner.log_record(record);
+        state.last = Some(Last {
+            verbosity: record.verbosity,
+            target: record.target.to_owned(),
+            text,
+            repeated: 0,
+            reported: now,
+        });
+    }
+}
+
+/// まだ記録していない繰り返しと破棄の数を記録します。
+impl<L: Logger> Drop for Throttle<L> {
+    fn drop(&mut self) {
+        let state = self.state.get_mut().unwrap_or_else(|e| e.into_inner());
+        let last = state.last.take();
+        let mut buckets: Vec<_> = state.buckets.drain().collect();
+        if let Some(last) = last {
+            self.report_repeats(&last);
+        }
+        buckets.sort_by_key(|&(verbosity, _)| verbosity);
+        for (verbosity, mut bucket) in buckets {
+            self.report_drops(verbosity, &mut bucket);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::panic::catch_unwind;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::mod_13_4::memory::Memory;
+
+    /// テストごとに進められる時計を作ります。`advance` は経過させるミリ秒です。
+    fn clock() -> (impl Fn() -> Instant + Send + Sync, impl Fn(u64)) {
+        let start = Instant::now();
+        let elapsed = Arc::new(AtomicU64::new(0));
+        let now = {
+            let elapsed = elapsed.clone();
+            move || start + Duration::from_millis(elapsed.load(Ordering::SeqCst))
+        };
+        let advance = move |millis| {
+            elapsed.fetch_add(millis, Ordering::SeqCst);
+        };
+        (now, advance)
+    }
+
+    const GENEROUS: Rate = Rate { per_second: 1000.0, burst: 1000.0 };
+
+    #[test]
+    fn collapses_repeats() {
+        let (now, advance) = clock();
+        let memory = Arc::new(Memory::default());
+        let logger = Throttle::with_clock(memory.clone(), GENEROUS, now);
+        for _ in 0..58 {
+            logger.log(1, "disk full");
+        }
+        logger.log(2, "disk full");
+        logger.log_target("db", 2, "disk full", &[("free", 0i64.into())]);
+        logger.log_target("db", 2, "disk full", &[("free", 0i64.into())]);
+        logger.log_target("db", 2, "disk full", &[("free", 1i64.into())]);
+        assert_eq!(
+            memory.take(),
+            [
+                "1 disk full",
+                "1 last message repeated 57 times",
+                "2 disk full",
+                "2 db: disk full free=0",
+                "2 db: last message repeated 1 times",
+                "2 db: disk full free=1",
+            ]
+        );
+
+        // 繰り返しが続いている間も、間隔ごとに数を記録します。
+        for _ in 0..10 {
+            logger.log_target("db", 2, "disk full", &[("free", 1i64.into())]);
+            advance(5_000);
+        }
+        assert_eq!(memory.take(), ["2 db: last message repeated 7 times"]);
+        drop(logger);
+        assert_eq!(memory.take(), ["2 db: last message repeated 3 times"]);
+    }
+
+    #[test]
+    fn limits_each_verbosity_separately() {
+        let (now, advance) = clock();
+        let memory = Arc::new(Memory::default());
+        let rate = Rate { per_second: 2.0, burst: 3.0 };
+        let logger = Throttle::with_clock(memory.clone(), rate, now);
+        for i in 0..5 {
+            logger.log(3, &format!("debug {i}"));
+        }
+        logger.log(1, "error");
+        assert_eq!(
+            memory.take(),
+            ["3 debug 0", "3 debug 1", "3 debug 2", "1 error"]
+        );
+
+        // 0.5 秒で 1 つ補充されます。
+        advance(400);
+        logger.log(3, "debug 5");
+        advance(100);
+        logger.log(3, "debug 6");
+        assert_eq!(memory.take(), ["3 rate limit dropped 3 messages", "3 debug 6"]);
+
+        // 長く待っても、ためられるのは `burst` までです。
+        advance(60_000);
+        for i in 7..12 {
+            logger.log(3, &format!("debug {i}"));
+        }
+        drop(logger);
+        assert_eq!(
+            memory.take(),
+            [
+                "3 debug 7",
+                "3 debug 8",
+                "3 debug 9",
+                "3 rate limit dropped 2 messages",
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_rates_that_drop_everything() {
+        for rate in [
+            Rate { per_second: 1.0, burst: 0.5 },
+            Rate { per_second: 1.0, burst: f64::NAN },
+            Rate { per_second: -1.0, burst: 2.0 },
+            Rate { per_second: f64::NAN, burst: 2.0 },
+        ] {
+            let throttle = catch_unwind(|| Throttle::new(Memory::default(), rate));
+            assert!(throttle.is_err(), "{rate:?}");
+        }
+    }
+
+    #[test]
+    fn repeats_do_not_use_tokens() {
+        let (now, _) = clock();
+        let memory = Arc::new(Memory::default());
+        let rate = Rate { per_second: 0.0, burst: 2.0 };
+        let logger = Throttle::with_clock(memory.clone(), rate, now);
+        for _ in 0..100 {
+            logger.log(1, "timeout");
+        }
+        logger.log(1, "retrying");
+        logger.log(1, "given up");
+        logger.log(1, "given up");
+        assert_eq!(
+            memory.take(),
+            ["1 timeout", "1 last message repeated 99 times", "1 retrying"]
+        );
+        drop(logger);
+        assert_eq!(memory.take(), ["1 rate limit dropped 2 messages"]);
+    }
+}